@@ -1,16 +1,51 @@
 use chrono::prelude::*;
 use binding::config::Config;
 use binding::metric::Metric;
+use plugin::metric_name::MetricName;
+use plugin::metric_template::MetricTemplate;
+use plugin::error::ReportError;
+use plugin::metric_kind::MetricKind;
+use plugin::metric_handle::MetricHandle;
+use plugin::rollup::RollupRule;
+use plugin::expression::Expression;
+use plugin::empty_policy::EmptyPolicy;
+use plugin::summary::MetricSummary;
+use plugin::attributes::Attributes;
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::fmt;
 
-#[derive(Debug, Clone)]
+static NEXT_COMPONENT_ID: AtomicUsize = AtomicUsize::new(0);
+const OVERFLOW_SEGMENT: &str = "Other";
+const DROPPED_NAMES_METRIC: &str = "Component/Supportability/MetricNames/Dropped[names]";
+
+#[derive(Debug)]
 pub struct Component{
     pub name: String,
     pub guid: String,
-    pub metrics: Vec<Metric>,
+    pub attributes: Attributes,
+    id: usize,
+    metrics: Vec<Slot>,
+    free_slots: Vec<usize>,
+    metric_index: HashMap<String, usize>,
+    templates: HashMap<String, (MetricTemplate, MetricKind)>,
+    rollups: Vec<RollupRule>,
+    derived_metrics: Vec<(MetricName, Expression)>,
+    dynamic_metrics: usize,
+    dynamic_metric_limit: usize,
+    dropped_names: HashSet<String>,
     last_delivered_at: Option<i64>
 }
 
+/// Place of a metric in its component. Evicting the metric empties the slot and bumps its
+/// generation, so handles to the evicted metric stay unknown after the slot is reused.
+#[derive(Debug, Clone)]
+struct Slot{
+    generation: u32,
+    dynamic: bool,
+    metric: Option<Metric>
+}
+
 impl fmt::Display for Component {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "Name: {}, GUID: {}, Metrics: {}, Last delivered at: {}",
@@ -18,71 +53,396 @@ impl fmt::Display for Component {
     }
 }
 
+/// A clone gets its own id, so handles issued by the original never resolve against the clone.
+impl Clone for Component {
+    fn clone(&self) -> Self{
+        Component{
+            name: self.name.clone(),
+            guid: self.guid.clone(),
+            attributes: self.attributes.clone(),
+            id: NEXT_COMPONENT_ID.fetch_add(1, Ordering::Relaxed),
+            metrics: self.metrics.clone(),
+            free_slots: self.free_slots.clone(),
+            metric_index: self.metric_index.clone(),
+            templates: self.templates.clone(),
+            rollups: self.rollups.clone(),
+            derived_metrics: self.derived_metrics.clone(),
+            dynamic_metrics: self.dynamic_metrics,
+            dynamic_metric_limit: self.dynamic_metric_limit,
+            dropped_names: self.dropped_names.clone(),
+            last_delivered_at: self.last_delivered_at
+        }
+    }
+}
+
 impl Component{
     pub fn new(name: String, guid: String) -> Self{
         Component{
-            name: name,
-            guid: guid,
+            name,
+            guid,
+            attributes: Attributes::new(),
+            id: NEXT_COMPONENT_ID.fetch_add(1, Ordering::Relaxed),
             metrics: vec![],
+            free_slots: vec![],
+            metric_index: HashMap::new(),
+            templates: HashMap::new(),
+            rollups: vec![],
+            derived_metrics: vec![],
+            dynamic_metrics: 0,
+            dynamic_metric_limit: usize::MAX,
+            dropped_names: HashSet::new(),
             last_delivered_at: None
         }
     }
 
     fn display_metrics(&self) -> String{
         let mut metrics = String::new();
-        for metric in &self.metrics{
+        for metric in self.metrics(){
             metrics = format!("{}", metric);
         }
         metrics
     }
 
-    pub fn key(&self) -> String{
-        format!("{}{}", self.name, self.guid)
+    pub fn id(&self) -> usize{
+        self.id
     }
 
+    /// Name and GUID, which together identify a registered component.
+    pub fn key(&self) -> (String, String){
+        (self.name.clone(), self.guid.clone())
+    }
+
+    /// Seconds since the last delivery. Metrics with a cumulative `ResetPolicy` cover a longer span.
     pub fn duration(&self) -> i64{
-        if self.last_delivered_at.is_none(){
-            let config = Config::new();
-            return config.deliver_cycle();
-        }else{
-            let now: DateTime<Utc> = Utc::now();
-            return now.timestamp() - self.last_delivered_at.unwrap();
+        let now: DateTime<Utc> = Utc::now();
+        match self.last_delivered_at{
+            Some(last_delivered_at) => now.timestamp() - last_delivered_at,
+            None => Config::new().deliver_cycle()
         }
+    }
 
+    pub fn add_metric(&mut self, name: &MetricName) -> MetricHandle{
+        self.add_metric_with_kind(name, MetricKind::Timeslice)
     }
 
-    pub fn add_metric(&mut self, name: String){
-        let metric = Metric::new(name);
-        self.metrics.push(metric);
+    pub fn add_metric_with_kind(&mut self, name: &MetricName, kind: MetricKind) -> MetricHandle{
+        self.insert_metric(name, kind, false)
     }
 
-    pub fn report_metric(&mut self, metric_name: String, value: f64,
-        options: Option<(u64, f64, f64, f64)>) -> f64{
-        let mut old_value = 0f64;
-        for metric in &mut self.metrics{
-            if metric.name == metric_name{
-                let m = Metric::new_valued(metric_name, value, options);
-                old_value = metric.aggregate(&m);
-                break;
+    fn insert_metric(&mut self, name: &MetricName, kind: MetricKind, dynamic: bool) -> MetricHandle{
+        let metric_name = name.to_string();
+        if let Some(handle) = self.metric_handle(&metric_name){
+            return handle;
+        }
+        let metric = Some(Metric::new(name, kind));
+        let index = match self.free_slots.pop(){
+            Some(index) => {
+                let slot = &mut self.metrics[index];
+                slot.dynamic = dynamic;
+                slot.metric = metric;
+                index
+            },
+            None => {
+                self.metrics.push(Slot{ generation: 0, dynamic, metric });
+                self.metrics.len() - 1
             }
+        };
+        self.metric_index.insert(metric_name, index);
+        self.handle(index)
+    }
+
+    fn handle(&self, index: usize) -> MetricHandle{
+        MetricHandle::new(self.id, index, self.metrics[index].generation)
+    }
+
+    pub fn metric_handle(&self, metric_name: &str) -> Option<MetricHandle>{
+        self.metric_index.get(metric_name).map(|&index| self.handle(index))
+    }
+
+    pub fn metrics(&self) -> impl Iterator<Item = &Metric>{
+        self.metrics.iter().filter_map(|slot| slot.metric.as_ref())
+    }
+
+    /// Metric `handle` refers to, unless it was evicted.
+    pub fn metric(&self, handle: MetricHandle) -> Option<&Metric>{
+        match self.metrics.get(handle.metric){
+            Some(slot) if slot.generation == handle.generation => slot.metric.as_ref(),
+            _ => None
         }
-        old_value
     }
 
-    pub fn get_metric(&self, metric_name: String) -> Option<&Metric>{
-        for metric in &self.metrics{
-            if metric.name == metric_name{
-                return Some(metric);
+    pub fn metric_mut(&mut self, handle: MetricHandle) -> Option<&mut Metric>{
+        match self.metrics.get_mut(handle.metric){
+            Some(slot) if slot.generation == handle.generation => slot.metric.as_mut(),
+            _ => None
+        }
+    }
+
+    pub fn add_template(&mut self, template: MetricTemplate, kind: MetricKind){
+        self.templates.insert(template.to_string(), (template, kind));
+    }
+
+    pub fn add_rollup(&mut self, rule: RollupRule){
+        self.rollups.push(rule);
+    }
+
+    /// Rollup summaries for the current interval, computed from the live metrics matching each rule.
+    pub fn rollup_summaries(&self, ttl: u32, default: EmptyPolicy) -> Vec<(String, MetricSummary)>{
+        let mut rollups = vec![];
+        for rule in &self.rollups{
+            let summaries: Vec<MetricSummary> = self.metrics()
+                .filter(|metric| metric.is_live(ttl) && rule.matches(&metric.name))
+                .filter_map(|metric| metric.interval_summary(default))
+                .collect();
+            if let Some(summary) = rule.combine(&summaries){
+                rollups.push((rule.target().to_string(), summary));
             }
         }
-        None
+        rollups
     }
 
-    pub fn last_delivered_now(&mut self){
-        self.last_delivered_at = Some(Utc::now().timestamp());
-        for metric in &mut self.metrics{
-            metric.reset();
+    pub fn add_derived_metric(&mut self, name: MetricName, expression: Expression){
+        self.derived_metrics.push((name, expression));
+    }
+
+    /// Derived metric summaries for the current interval. Expressions see the interval average
+    /// of every live metric, every rollup in `rollups` and every derived metric declared before them.
+    pub fn derived_summaries(&self, ttl: u32, default: EmptyPolicy,
+        rollups: &[(String, MetricSummary)]) -> Vec<(String, MetricSummary)>{
+        if self.derived_metrics.is_empty(){
+            return vec![];
+        }
+        let mut values = HashMap::new();
+        let summaries = self.metrics()
+            .filter(|metric| metric.is_live(ttl))
+            .filter_map(|metric| metric.interval_summary(default).map(|summary| (metric.name.clone(), summary)))
+            .chain(rollups.iter().cloned());
+        for (name, summary) in summaries{
+            if !summary.is_empty(){
+                values.insert(name, summary.total / summary.count as f64);
+            }
+        }
+        let mut derived = vec![];
+        for (name, expression) in &self.derived_metrics{
+            if let Some(value) = expression.evaluate(&values){
+                values.insert(name.to_string(), value);
+                derived.push((name.to_string(), MetricSummary::from_value(value)));
+            }
+        }
+        derived
+    }
+
+    pub fn set_metric_limit(&mut self, limit: usize){
+        self.dynamic_metric_limit = limit;
+    }
+
+    /// Number of metrics created while reporting, from templates or by auto-creation.
+    pub fn dynamic_metrics(&self) -> usize{
+        self.dynamic_metrics
+    }
+
+    /// Handle of the metric rendered from `template` with `labels`, creating the metric if this
+    /// is the first report for these label values.
+    pub fn template_metric(&mut self, template: &str, labels: &[(&str, &str)],
+        allow_new: bool) -> Result<MetricHandle, ReportError>{
+        let (name, overflow, kind) = match self.templates.get(template){
+            Some((metric_template, kind)) => {
+                let overflow_labels: Vec<(&str, &str)> = metric_template.labels().into_iter()
+                    .map(|label| (label, OVERFLOW_SEGMENT)).collect();
+                (metric_template.render(labels)?, metric_template.render(&overflow_labels)?, kind.clone())
+            },
+            None => return Err(ReportError::UnknownTemplate{
+                component: self.guid.clone(),
+                template: template.to_string()
+            })
+        };
+        self.dynamic_metric(&name, kind, &overflow, allow_new)
+    }
+
+    /// Creates a timeslice metric named `metric_name` unless the component already has one.
+    pub fn ensure_metric(&mut self, metric_name: &str,
+        allow_new: bool) -> Result<MetricHandle, ReportError>{
+        if let Some(handle) = self.metric_handle(metric_name){
+            return Ok(handle);
+        }
+        let name = MetricName::parse(metric_name)?;
+        let overflow = name.with_last_segment(OVERFLOW_SEGMENT)?;
+        self.dynamic_metric(&name, MetricKind::Timeslice, &overflow, allow_new)
+    }
+
+    /// Handle of metric `name`, creating it if the component is below its metric limit and
+    /// `allow_new` is set. Otherwise the report is folded into the `overflow` metric and the name
+    /// is counted in the dropped names supportability metric. Counters are dropped instead of
+    /// folded, since readings of different counters cannot be turned into one delta.
+    fn dynamic_metric(&mut self, name: &MetricName, kind: MetricKind, overflow: &MetricName,
+        allow_new: bool) -> Result<MetricHandle, ReportError>{
+        let metric_name = name.to_string();
+        if let Some(handle) = self.metric_handle(&metric_name){
+            return Ok(handle);
+        }
+        if allow_new && self.dynamic_metrics < self.dynamic_metric_limit{
+            self.dynamic_metrics += 1;
+            info!(target: "agent", "Created metric {} on component {}.", metric_name, self.guid);
+            return Ok(self.insert_metric(name, kind, true));
+        }
+        if self.dropped_names.insert(metric_name.clone()){
+            warn!(target: "agent", "Metric limit reached on component {}. Reporting {} as {}.",
+                self.guid, metric_name, overflow);
+            if let Ok(dropped_name) = MetricName::parse(DROPPED_NAMES_METRIC){
+                let handle = self.add_metric(&dropped_name);
+                if let Some(metric) = self.metric_mut(handle){
+                    metric.record(1f64);
+                }
+            }
+        }
+        if let MetricKind::Counter{ .. } = kind{
+            return Err(ReportError::CardinalityLimit{ component: self.guid.clone(), metric: metric_name });
+        }
+        Ok(self.add_metric_with_kind(overflow, kind))
+    }
+
+    pub fn get_metric(&self, metric_name: String) -> Option<&Metric>{
+        match self.metric_index.get(&metric_name){
+            Some(&index) => self.metrics[index].metric.as_ref(),
+            None => None
+        }
+    }
+
+    /// Called after each successful delivery. Metrics created while reporting that have not been
+    /// reported for `ttl` deliver cycles are evicted, making room for new ones under the metric limit.
+    pub fn last_delivered_now(&mut self, ttl: u32){
+        let now = Utc::now().timestamp();
+        self.last_delivered_at = Some(now);
+        self.dropped_names.clear();
+        for (index, slot) in self.metrics.iter_mut().enumerate(){
+            let expired = match slot.metric{
+                Some(ref mut metric) => {
+                    metric.delivered(now);
+                    slot.dynamic && !metric.is_live(ttl)
+                },
+                None => false
+            };
+            if expired{
+                if let Some(metric) = slot.metric.take(){
+                    info!(target: "agent", "Evicted metric {} from component {}, not reported for {} deliver cycles.",
+                        metric.name, self.guid, ttl);
+                    self.metric_index.remove(&metric.name);
+                }
+                slot.generation = slot.generation.wrapping_add(1);
+                self.dynamic_metrics -= 1;
+                self.free_slots.push(index);
+            }
         }
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::{Component, DROPPED_NAMES_METRIC};
+    use plugin::empty_policy::EmptyPolicy;
+    use plugin::error::ReportError;
+    use plugin::metric_kind::MetricKind;
+    use plugin::metric_name::MetricName;
+    use plugin::metric_template::MetricTemplate;
+    use plugin::reset_policy::ResetPolicy;
+    use plugin::summary::MetricSummary;
+    use std::time::Duration;
+
+    fn component_with_template(template: &str, kind: MetricKind) -> Component{
+        let mut component = Component::new("mq".to_string(), "com.example.mq".to_string());
+        component.set_metric_limit(1);
+        component.add_template(MetricTemplate::parse(template).unwrap(), kind);
+        component
+    }
+
+    fn interval_summary(component: &Component, metric_name: &str) -> Option<MetricSummary>{
+        component.get_metric(metric_name.to_string())
+            .and_then(|metric| metric.interval_summary(EmptyPolicy::Omit))
+    }
+
+    #[test]
+    fn clone_gets_a_fresh_id_and_keeps_its_metrics(){
+        let mut component = Component::new("db".to_string(), "com.example.db".to_string());
+        let name = MetricName::parse("Component/Queries[queries]").unwrap();
+        let handle = component.add_metric(&name);
+        let clone = component.clone();
+        assert_ne!(clone.id(), component.id());
+        assert_ne!(clone.metric_handle(&name.to_string()), Some(handle));
+        assert_eq!(clone.metric_handle(&name.to_string()).map(|handle| handle.metric), Some(handle.metric));
+    }
+
+    #[test]
+    fn reports_over_the_limit_are_folded_into_the_overflow_metric(){
+        let template = "Component/Queue/{queue}/Depth[messages]";
+        let mut component = component_with_template(template, MetricKind::Timeslice);
+        for &(queue, depth) in &[("emails", 12f64), ("invoices", 3f64), ("refunds", 5f64), ("invoices", 1f64)]{
+            let handle = component.template_metric(template, &[("queue", queue)], true).unwrap();
+            component.metric_mut(handle).unwrap().record(depth);
+        }
+        assert_eq!(component.dynamic_metrics(), 1);
+        assert_eq!(interval_summary(&component, "Component/Queue/emails/Depth[messages]"),
+            Some(MetricSummary::from_value(12f64)));
+        assert_eq!(interval_summary(&component, "Component/Queue/Other/Depth[messages]"),
+            MetricSummary::from_samples(&[3f64, 5f64, 1f64]).ok());
+        assert_eq!(interval_summary(&component, DROPPED_NAMES_METRIC),
+            MetricSummary::from_samples(&[1f64, 1f64]).ok());
+    }
+
+    #[test]
+    fn counters_over_the_limit_are_dropped_and_counted(){
+        let template = "Component/Queue/{queue}/Received[messages]";
+        let mut component = component_with_template(template, MetricKind::counter());
+        assert!(component.template_metric(template, &[("queue", "emails")], true).is_ok());
+        for _ in 0..2{
+            assert_eq!(component.template_metric(template, &[("queue", "invoices")], true),
+                Err(ReportError::CardinalityLimit{
+                    component: "com.example.mq".to_string(),
+                    metric: "Component/Queue/invoices/Received[messages]".to_string()
+                }));
+        }
+        assert!(component.get_metric("Component/Queue/Other/Received[messages]".to_string()).is_none());
+        assert_eq!(interval_summary(&component, DROPPED_NAMES_METRIC), Some(MetricSummary::from_value(1f64)));
+    }
+
+    #[test]
+    fn expired_dynamic_metrics_are_evicted_and_free_their_slot(){
+        let template = "Component/Queue/{queue}/Depth[messages]";
+        let mut component = component_with_template(template, MetricKind::Timeslice);
+        let emails = component.template_metric(template, &[("queue", "emails")], true).unwrap();
+        component.metric_mut(emails).unwrap().record(12f64);
+        component.last_delivered_now(2);
+        component.last_delivered_now(2);
+        assert!(component.metric(emails).is_some());
+        component.last_delivered_now(2);
+        assert!(component.metric(emails).is_none());
+        assert!(component.get_metric("Component/Queue/emails/Depth[messages]".to_string()).is_none());
+        assert_eq!(component.dynamic_metrics(), 0);
+
+        let invoices = component.template_metric(template, &[("queue", "invoices")], true).unwrap();
+        component.metric_mut(invoices).unwrap().record(3f64);
+        assert_eq!(invoices.metric, emails.metric);
+        assert!(component.metric(emails).is_none());
+        assert_eq!(interval_summary(&component, "Component/Queue/invoices/Depth[messages]"),
+            Some(MetricSummary::from_value(3f64)));
+        assert!(component.get_metric("Component/Queue/Other/Depth[messages]".to_string()).is_none());
+    }
+
+    #[test]
+    fn duration_is_the_deliver_interval_whatever_the_reset_policies(){
+        let mut component = Component::new("web".to_string(), "com.example.web".to_string());
+        let served = component.add_metric(&MetricName::parse("Component/Served[requests]").unwrap());
+        let errors = component.add_metric(&MetricName::parse("Component/Errors[requests]").unwrap());
+        component.metric_mut(served).unwrap().reset_policy = ResetPolicy::Never;
+        component.metric_mut(errors).unwrap().reset_policy = ResetPolicy::Every(Duration::from_secs(3600));
+        component.metric_mut(served).unwrap().record(5f64);
+        component.metric_mut(errors).unwrap().record(1f64);
+        component.last_delivered_now(0);
+        component.metric_mut(served).unwrap().record(7f64);
+        assert_eq!(interval_summary(&component, "Component/Served[requests]"),
+            MetricSummary::from_samples(&[5f64, 7f64]).ok());
+        assert_eq!(interval_summary(&component, "Component/Errors[requests]"), Some(MetricSummary::from_value(1f64)));
+
+        component.last_delivered_at = component.last_delivered_at.map(|at| at - 60);
+        assert!((60..62).contains(&component.duration()));
+    }
+}
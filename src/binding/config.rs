@@ -1,13 +1,39 @@
 use std::fs::File;
 use std::io::prelude::*;
 use serde_yaml::from_str as from_yaml;
+use plugin::empty_policy::EmptyPolicy;
+use plugin::invalid_value_policy::InvalidValuePolicy;
 
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
 pub struct Config{
     endpoint: String,
     log4rs_file: String,
     deliver_cycle: i64,
-    poll_cycle: i64
+    poll_cycle: i64,
+    #[serde(default = "default_component_metric_limit", alias = "template_metric_limit")]
+    component_metric_limit: usize,
+    #[serde(default = "default_agent_metric_limit")]
+    agent_metric_limit: usize,
+    #[serde(default)]
+    metric_ttl: u32,
+    #[serde(default)]
+    empty_metrics: EmptyPolicy,
+    #[serde(default)]
+    invalid_values: InvalidValuePolicy,
+    #[serde(default = "default_discovery_cycle")]
+    discovery_cycle: i64
+}
+
+fn default_component_metric_limit() -> usize{
+    100
+}
+
+fn default_agent_metric_limit() -> usize{
+    1000
+}
+
+fn default_discovery_cycle() -> i64{
+    60
 }
 
 impl Config {
@@ -19,7 +45,13 @@ impl Config {
                     endpoint: "https://platform-api.newrelic.com/platform/v1/metrics".into(),
                     log4rs_file: "log4rs.yml".into(),
                     deliver_cycle: 60,
-                    poll_cycle: 20
+                    poll_cycle: 20,
+                    component_metric_limit: default_component_metric_limit(),
+                    agent_metric_limit: default_agent_metric_limit(),
+                    metric_ttl: 0,
+                    empty_metrics: EmptyPolicy::Omit,
+                    invalid_values: InvalidValuePolicy::Reject,
+                    discovery_cycle: default_discovery_cycle()
                 }
             }
         }
@@ -58,6 +90,30 @@ impl Config {
         self.poll_cycle
     }
 
+    pub fn discovery_cycle(&self) -> i64{
+        self.discovery_cycle
+    }
+
+    pub fn component_metric_limit(&self) -> usize{
+        self.component_metric_limit
+    }
+
+    pub fn agent_metric_limit(&self) -> usize{
+        self.agent_metric_limit
+    }
+
+    pub fn metric_ttl(&self) -> u32{
+        self.metric_ttl
+    }
+
+    pub fn empty_metrics(&self) -> EmptyPolicy{
+        self.empty_metrics
+    }
+
+    pub fn invalid_values(&self) -> InvalidValuePolicy{
+        self.invalid_values
+    }
+
     pub fn get_endpoint(&self) -> String{
         self.endpoint.to_string()
     }
@@ -66,3 +122,17 @@ impl Config {
         self.log4rs_file.to_string()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::Config;
+    use serde_yaml::from_str as from_yaml;
+
+    #[test]
+    fn template_metric_limit_is_read_as_component_metric_limit(){
+        let config: Config = from_yaml("endpoint: http://localhost\nlog4rs_file: log4rs.yml\n\
+            deliver_cycle: 60\npoll_cycle: 20\ntemplate_metric_limit: 7\n").unwrap();
+        assert_eq!(config.component_metric_limit(), 7);
+        assert_eq!(config.agent_metric_limit(), 1000);
+    }
+}
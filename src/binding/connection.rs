@@ -13,14 +13,14 @@ pub struct Connection{
 impl Connection {
     pub fn new(data: Value, license_key: String) -> Self{
         Connection{
-            data: data,
-            license_key: license_key,
+            data,
+            license_key,
             url: Config::new().get_endpoint()
         }
     }
 
     pub fn send_request(&self) -> bool{
-        info!(target: "agent", "\tJSON Payload: {}", self.data.to_string());
+        info!(target: "agent", "\tJSON Payload: {}", self.data);
         let body = self.data.clone();
         let body = body.to_string();
         let mut body = body.as_bytes();
@@ -50,7 +50,7 @@ impl Connection {
             let _ = transfer.header_function(|hh| {
                 let header = String::from_utf8(hh.to_vec()).unwrap();
                 if header.contains("HTTP/1.1"){
-                    status_code = usize::from_str_radix(header.split(" ").nth(1).unwrap(), 10).unwrap();
+                    status_code = header.split(" ").nth(1).unwrap().parse::<usize>().unwrap();
                 }
                 true
             });
@@ -63,9 +63,9 @@ impl Connection {
 
         if response.is_err(){
             error!(target: "agent", "Connection Error: {}", response.err().unwrap());
-            return false;
+            false
         }else{
-            return self.evaluate_response(status_code, response_body);
+            self.evaluate_response(status_code, response_body)
         }
     }
 
@@ -87,7 +87,7 @@ impl Connection {
                 error!(target: "agent", "Collector temporarily unavailable. Continuing.");
             },
             _ => {
-                if response_body.len() > 0 {
+                if !response_body.is_empty() {
                     last_result = unjson(&response_body).unwrap();
                 }else{
                     last_result = json!({"error": "no data returned"});
@@ -95,8 +95,8 @@ impl Connection {
                 return_status = Some(format!("FAILED {}, {}", response_code, last_result["error"]));
             }
         }
-        if return_status.is_some(){
-            error!(target: "agent", "{}", return_status.as_ref().unwrap());
+        if let Some(ref status) = return_status{
+            error!(target: "agent", "{}", status);
         }
         return_status.is_none()
     }
@@ -1,17 +1,43 @@
 use serde_json::value::Value;
 use chrono::prelude::*;
 use binding::component::Component;
+use binding::metric::{Metric, summary_hash};
 use binding::request::Request;
+use plugin::summary::MetricSummary;
+use plugin::unit::Quantity;
+use plugin::error::{ReportError, ComponentError};
+use plugin::metric_handle::MetricHandle;
+use plugin::empty_policy::EmptyPolicy;
+use plugin::invalid_value_policy::InvalidValuePolicy;
+use plugin::smoothing::Smoothing;
+use plugin::statistics::Statistics;
+use plugin::reset_policy::ResetPolicy;
+use plugin::attributes::{encode_name, validate_attribute};
+use plugin::snapshot::{Snapshot, ComponentSnapshot};
+use plugin::recorder::Pending;
+use plugin::discovery::ComponentSpec;
+use plugin::metric_template::MetricTemplate;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fmt;
 
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 pub struct Context{
     components: Vec<Component>,
+    key_index: HashMap<(String, String), usize>,
+    guid_index: HashMap<String, Vec<usize>>,
+    id_index: HashMap<usize, usize>,
+    pub auto_create_metrics: bool,
+    pub agent_metric_limit: usize,
+    pub metric_ttl: u32,
+    pub empty_policy: EmptyPolicy,
+    pub invalid_value_policy: InvalidValuePolicy,
+    dynamic_metrics: usize,
     pub license_key: String,
     pub version: String,
     pub host: String,
     pub pid: u64,
-    pub last_reported: Option<i64>
+    pub last_reported: Option<i64>,
+    last_delivered: Option<Snapshot>
 }
 
 impl fmt::Display for Context {
@@ -25,12 +51,22 @@ impl Context{
     pub fn new(license_key: String, version: String, host: String,
         pid: u64) -> Self{
         Context{
-            version: version,
-            host: host,
-            pid: pid,
-            license_key: license_key,
+            version,
+            host,
+            pid,
+            license_key,
             last_reported: None,
-            components: vec![]
+            last_delivered: None,
+            components: vec![],
+            key_index: HashMap::new(),
+            guid_index: HashMap::new(),
+            id_index: HashMap::new(),
+            auto_create_metrics: false,
+            agent_metric_limit: usize::MAX,
+            metric_ttl: 0,
+            empty_policy: EmptyPolicy::Omit,
+            invalid_value_policy: InvalidValuePolicy::Reject,
+            dynamic_metrics: 0
         }
     }
 
@@ -42,23 +78,354 @@ impl Context{
         components
     }
 
-    pub fn register_component(&mut self, component: Component){
+    pub fn register_component(&mut self, component: Component) -> Result<(), ComponentError>{
+        if self.key_index.contains_key(&component.key()){
+            return Err(ComponentError::Duplicate{ name: component.name, guid: component.guid });
+        }
         self.components.push(component);
+        self.reindex();
+        Ok(())
+    }
+
+    /// Removes a component. Values it aggregated since the last delivery are dropped, and its
+    /// metric handles become unknown.
+    pub fn unregister_component(&mut self, name: &str, guid: &str) -> Result<Component, ComponentError>{
+        match self.position(name, guid){
+            Some(index) => {
+                let component = self.components.remove(index);
+                self.reindex();
+                Ok(component)
+            },
+            None => Err(ComponentError::NotRegistered{ name: name.to_string(), guid: guid.to_string() })
+        }
     }
 
-    pub fn report_metric(&mut self, component_guid: String, metric_name: String, value: f64,
-        options: Option<(u64, f64, f64, f64)>) -> f64{
-        let mut old_value = 0f64;
-        for component in &mut self.components{
-            if component.guid == component_guid{
-                old_value = component.report_metric(metric_name, value, options);
-                break;
+    /// Swaps the registered component with the same name and GUID for `component`, keeping its
+    /// place in the payload.
+    pub fn replace_component(&mut self, component: Component) -> Result<Component, ComponentError>{
+        match self.key_index.get(&component.key()).cloned(){
+            Some(index) => {
+                let replaced = std::mem::replace(&mut self.components[index], component);
+                self.reindex();
+                Ok(replaced)
+            },
+            None => Err(ComponentError::NotRegistered{ name: component.name, guid: component.guid })
+        }
+    }
+
+    /// Registers the components in `specs` that are not registered yet and unregisters the ones
+    /// in `discovered` that are no longer wanted. `discovered` keeps the name and GUID of every
+    /// component registered this way, so components registered by hand are left alone.
+    pub fn sync_discovered(&mut self, discovered: &mut HashSet<(String, String)>,
+        specs: Vec<ComponentSpec>, metric_limit: usize){
+        let wanted: HashSet<(String, String)> = specs.iter()
+            .map(|spec| (spec.name.clone(), spec.guid.clone())).collect();
+        // Components unregistered by hand since the last discovery are discovered again.
+        discovered.retain(|(name, guid)| self.is_registered(name, guid));
+        for (name, guid) in discovered.difference(&wanted){
+            info!(target: "agent", "Component {} ({}) is gone, unregistering it.", name, guid);
+            if let Err(e) = self.unregister_component(name, guid){
+                warn!(target: "agent", "Could not unregister discovered component. Error: {}", e);
+            }
+        }
+        discovered.retain(|key| wanted.contains(key));
+        for spec in specs{
+            if self.is_registered(&spec.name, &spec.guid){
+                continue;
+            }
+            let mut component = Component::new(spec.name, spec.guid);
+            component.set_metric_limit(metric_limit);
+            for template in spec.templates{
+                match MetricTemplate::parse(&template.template){
+                    Ok(metric_template) => component.add_template(metric_template, template.kind),
+                    Err(e) => error!(target: "agent", "Invalid metric template {:?}. Error: {}", template.template, e)
+                }
             }
+            info!(target: "agent", "Discovered component {} ({}).", component.name, component.guid);
+            let key = component.key();
+            match self.register_component(component){
+                Ok(()) => {
+                    discovered.insert(key);
+                },
+                Err(e) => error!(target: "agent", "Could not register discovered component. Error: {}", e)
+            }
+        }
+    }
+
+    pub fn is_registered(&self, name: &str, guid: &str) -> bool{
+        self.position(name, guid).is_some()
+    }
+
+    fn position(&self, name: &str, guid: &str) -> Option<usize>{
+        self.key_index.get(&(name.to_string(), guid.to_string())).cloned()
+    }
+
+    /// Rebuilds the lookup indexes after components were added or removed.
+    fn reindex(&mut self){
+        self.key_index.clear();
+        self.guid_index.clear();
+        self.id_index.clear();
+        for (index, component) in self.components.iter().enumerate(){
+            self.key_index.insert(component.key(), index);
+            self.guid_index.entry(component.guid.clone()).or_default().push(index);
+            self.id_index.insert(component.id(), index);
+        }
+        self.dynamic_metrics = self.components.iter().map(Component::dynamic_metrics).sum();
+    }
+
+    /// Finds the component a report by GUID alone goes to. Fails if several components share the GUID.
+    fn component_index(&self, component_guid: &str) -> Result<usize, ReportError>{
+        match self.guid_index.get(component_guid).map(Vec::as_slice){
+            Some(&[index]) => Ok(index),
+            Some(&[_, _, ..]) => Err(ReportError::AmbiguousComponent(component_guid.to_string())),
+            _ => Err(ReportError::UnknownComponent(component_guid.to_string()))
+        }
+    }
+
+    fn named_component_index(&self, component_name: &str, component_guid: &str) -> Result<usize, ReportError>{
+        self.position(component_name, component_guid).ok_or_else(|| ReportError::UnknownNamedComponent{
+            name: component_name.to_string(),
+            guid: component_guid.to_string()
+        })
+    }
+
+    /// Resolves a metric of the component at `index` by name, creating it first in auto-create mode.
+    fn resolve_metric(&mut self, index: usize, metric_name: &str) -> Result<MetricHandle, ReportError>{
+        if self.auto_create_metrics{
+            let allow_new = self.dynamic_metrics < self.agent_metric_limit;
+            self.track_dynamic_metrics(index, |component| component.ensure_metric(metric_name, allow_new))
+        }else{
+            let component = &self.components[index];
+            component.metric_handle(metric_name).ok_or_else(|| ReportError::UnknownMetric{
+                component: component.guid.clone(),
+                metric: metric_name.to_string()
+            })
+        }
+    }
+
+    fn report_labeled_at(&mut self, index: usize, template: &str,
+        labels: &[(&str, &str)], value: f64) -> Result<f64, ReportError>{
+        let allow_new = self.dynamic_metrics < self.agent_metric_limit;
+        let handle = self.track_dynamic_metrics(index, |component| component.template_metric(template, labels, allow_new))?;
+        self.record(handle, value)
+    }
+
+    /// Runs `f` on a component and keeps the agent-wide count of dynamic metrics up to date.
+    fn track_dynamic_metrics<F, R>(&mut self, index: usize, f: F) -> R
+        where F: FnOnce(&mut Component) -> R{
+        let component = &mut self.components[index];
+        let before = component.dynamic_metrics();
+        let result = f(component);
+        self.dynamic_metrics += component.dynamic_metrics() - before;
+        result
+    }
+
+    fn handle_metric(&self, handle: MetricHandle) -> Result<&Metric, ReportError>{
+        match self.id_index.get(&handle.component){
+            Some(&index) => self.components[index].metric(handle)
+                .ok_or(ReportError::UnknownHandle(handle)),
+            None => Err(ReportError::UnknownHandle(handle))
+        }
+    }
+
+    fn handle_metric_mut(&mut self, handle: MetricHandle) -> Result<&mut Metric, ReportError>{
+        match self.id_index.get(&handle.component){
+            Some(&index) => self.components[index].metric_mut(handle)
+                .ok_or(ReportError::UnknownHandle(handle)),
+            None => Err(ReportError::UnknownHandle(handle))
+        }
+    }
+
+    pub fn metric_handle(&self, component_guid: &str, metric_name: &str) -> Option<MetricHandle>{
+        match self.component_index(component_guid){
+            Ok(index) => self.components[index].metric_handle(metric_name),
+            Err(_) => None
         }
-        old_value
     }
 
-    fn request_hash(&self) -> Value{
+    pub fn component_metric_handle(&self, component_name: &str, component_guid: &str,
+        metric_name: &str) -> Option<MetricHandle>{
+        match self.position(component_name, component_guid){
+            Some(index) => self.components[index].metric_handle(metric_name),
+            None => None
+        }
+    }
+
+    pub fn report_metric(&mut self, component_guid: String, metric_name: String,
+        value: f64) -> Result<f64, ReportError>{
+        let index = self.component_index(&component_guid)?;
+        let handle = self.resolve_metric(index, &metric_name)?;
+        self.record(handle, value)
+    }
+
+    pub fn report_summary(&mut self, component_guid: String, metric_name: String,
+        summary: MetricSummary) -> Result<f64, ReportError>{
+        let index = self.component_index(&component_guid)?;
+        let handle = self.resolve_metric(index, &metric_name)?;
+        self.record_summary(handle, summary)
+    }
+
+    pub fn report_quantity(&mut self, component_guid: String, metric_name: String,
+        quantity: Quantity) -> Result<f64, ReportError>{
+        let index = self.component_index(&component_guid)?;
+        let handle = self.resolve_metric(index, &metric_name)?;
+        self.record_quantity(handle, quantity)
+    }
+
+    pub fn report_labeled(&mut self, component_guid: String, template: String,
+        labels: &[(&str, &str)], value: f64) -> Result<f64, ReportError>{
+        let index = self.component_index(&component_guid)?;
+        self.report_labeled_at(index, &template, labels, value)
+    }
+
+    pub fn report_component_metric(&mut self, component_name: &str, component_guid: &str,
+        metric_name: String, value: f64) -> Result<f64, ReportError>{
+        let index = self.named_component_index(component_name, component_guid)?;
+        let handle = self.resolve_metric(index, &metric_name)?;
+        self.record(handle, value)
+    }
+
+    pub fn report_component_summary(&mut self, component_name: &str, component_guid: &str,
+        metric_name: String, summary: MetricSummary) -> Result<f64, ReportError>{
+        let index = self.named_component_index(component_name, component_guid)?;
+        let handle = self.resolve_metric(index, &metric_name)?;
+        self.record_summary(handle, summary)
+    }
+
+    pub fn report_component_quantity(&mut self, component_name: &str, component_guid: &str,
+        metric_name: String, quantity: Quantity) -> Result<f64, ReportError>{
+        let index = self.named_component_index(component_name, component_guid)?;
+        let handle = self.resolve_metric(index, &metric_name)?;
+        self.record_quantity(handle, quantity)
+    }
+
+    pub fn report_component_labeled(&mut self, component_name: &str, component_guid: &str,
+        template: String, labels: &[(&str, &str)], value: f64) -> Result<f64, ReportError>{
+        let index = self.named_component_index(component_name, component_guid)?;
+        self.report_labeled_at(index, &template, labels, value)
+    }
+
+    pub fn set_empty_policy(&mut self, handle: MetricHandle,
+        policy: EmptyPolicy) -> Result<(), ReportError>{
+        self.handle_metric_mut(handle)?.empty_policy = Some(policy);
+        Ok(())
+    }
+
+    pub fn set_reset_policy(&mut self, handle: MetricHandle,
+        policy: ResetPolicy) -> Result<(), ReportError>{
+        self.handle_metric_mut(handle)?.reset_policy = policy;
+        Ok(())
+    }
+
+    pub fn set_metric_attribute(&mut self, handle: MetricHandle, key: String,
+        value: String) -> Result<(), ReportError>{
+        validate_attribute(&key, &value)?;
+        self.handle_metric_mut(handle)?.attributes.insert(key, value);
+        Ok(())
+    }
+
+    pub fn add_smoothing(&mut self, handle: MetricHandle,
+        smoothing: Smoothing) -> Result<(), ReportError>{
+        self.handle_metric_mut(handle)?.add_smoothing(smoothing)
+    }
+
+    pub fn statistics(&self, handle: MetricHandle) -> Result<Statistics, ReportError>{
+        Ok(self.handle_metric(handle)?.statistics())
+    }
+
+    pub fn invalid_samples(&self, handle: MetricHandle) -> Result<u64, ReportError>{
+        Ok(self.handle_metric(handle)?.invalid_samples())
+    }
+
+    pub fn record(&mut self, handle: MetricHandle, value: f64) -> Result<f64, ReportError>{
+        let policy = self.invalid_value_policy;
+        let metric = self.handle_metric_mut(handle)?;
+        let value = metric.check_value(value, policy)?;
+        Ok(metric.record(value))
+    }
+
+    pub fn record_summary(&mut self, handle: MetricHandle,
+        summary: MetricSummary) -> Result<f64, ReportError>{
+        let metric = self.handle_metric_mut(handle)?;
+        metric.check_summary(&summary)?;
+        Ok(metric.aggregate(&summary))
+    }
+
+    pub fn record_quantity(&mut self, handle: MetricHandle,
+        quantity: Quantity) -> Result<f64, ReportError>{
+        let policy = self.invalid_value_policy;
+        let metric = self.handle_metric_mut(handle)?;
+        let value = metric.check_value(quantity.convert(&metric.unit)?, policy)?;
+        Ok(metric.record(value))
+    }
+
+    /// Applies what a `Recorder` aggregated for `handle` since its last drain.
+    pub(crate) fn apply_recorded(&mut self, handle: MetricHandle, pending: Pending) -> Result<(), ReportError>{
+        let metric = self.handle_metric_mut(handle)?;
+        metric.add_invalid_samples(pending.invalid_samples);
+        let mut samples = vec![pending.values];
+        for (unit, quantities) in pending.quantities{
+            samples.push(quantities.scaled(unit.convert(1f64, &metric.unit)?));
+        }
+        // Counters see their readings in the order they were taken.
+        samples.sort_by_key(|samples| samples.last.map(|(_, at)| at));
+        for samples in &samples{
+            metric.record_samples(samples);
+        }
+        if !pending.summary.is_empty(){
+            metric.check_summary(&pending.summary)?;
+            metric.aggregate(&pending.summary);
+        }
+        Ok(())
+    }
+
+    fn component_snapshot(&self, component: &Component) -> ComponentSnapshot{
+        let mut summaries = vec![];
+        let mut metric_attributes = BTreeMap::new();
+        for metric in component.metrics().filter(|metric| metric.is_live(self.metric_ttl)){
+            for (name, summary) in metric.summaries(self.empty_policy){
+                // Percentile and smoothing companions share the attributes of their metric.
+                if !metric.attributes.is_empty(){
+                    metric_attributes.insert(name.clone(), metric.attributes.clone());
+                }
+                summaries.push((name, summary));
+            }
+        }
+        let rollups = component.rollup_summaries(self.metric_ttl, self.empty_policy);
+        let derived = component.derived_summaries(self.metric_ttl, self.empty_policy, &rollups);
+        summaries.extend(rollups.into_iter().chain(derived));
+
+        let mut metrics = BTreeMap::new();
+        for (name, summary) in summaries{
+            // Sums of large values can still overflow, and a single non-finite number fails the whole payload.
+            if summary.is_finite(){
+                metrics.insert(name, summary);
+            }else{
+                error!(target: "agent", "Leaving metric {} out of the payload, summary is not finite: {}", name, summary);
+            }
+        }
+        ComponentSnapshot{
+            name: component.name.clone(),
+            guid: component.guid.clone(),
+            duration: component.duration(),
+            metrics,
+            attributes: component.attributes.clone(),
+            metric_attributes
+        }
+    }
+
+    pub fn snapshot(&self) -> Snapshot{
+        Snapshot{
+            taken_at: Utc::now().timestamp(),
+            components: self.components.iter().map(|component| self.component_snapshot(component)).collect()
+        }
+    }
+
+    pub fn last_delivered(&self) -> Option<&Snapshot>{
+        self.last_delivered.as_ref()
+    }
+
+    fn request_hash(&self, snapshot: &Snapshot) -> Value{
         let mut hash = json!({});
         hash["agent"] = json!({
             "host": self.host,
@@ -66,16 +433,20 @@ impl Context{
             "version": self.version
         });
         let mut components = vec![];
-        for component in &self.components{
+        // Components with nothing to send this interval are left out of the payload.
+        for component in snapshot.components.iter().filter(|component| !component.metrics.is_empty()){
             let mut metrics = json!({});
-            for metric in &component.metrics{
-                metrics[metric.to_hash().0] = json!(metric.to_hash().1);
+            for (name, summary) in &component.metrics{
+                match encode_name(name, &component.attributes_of(name)){
+                    Ok(name) => metrics[name] = summary_hash(summary),
+                    Err(e) => error!(target: "agent", "Leaving metric {} out of the payload, cannot encode its attributes. Error: {}", name, e)
+                }
             }
 
             components.push(json!({
                 "name": component.name,
                 "guid": component.guid,
-                "duration": component.duration(),
+                "duration": component.duration,
                 "metrics": metrics
             }));
         }
@@ -84,14 +455,155 @@ impl Context{
     }
 
     pub fn deliver(&mut self){
-        let mut request = Request::new(self.request_hash(), self.license_key.clone());
+        let snapshot = self.snapshot();
+        let mut request = Request::new(self.request_hash(&snapshot), self.license_key.clone());
         let success = request.send();
         if success{
             for component in &mut self.components{
-                component.last_delivered_now();
+                component.last_delivered_now(self.metric_ttl);
             }
+            self.dynamic_metrics = self.components.iter().map(Component::dynamic_metrics).sum();
+            self.last_delivered = Some(snapshot);
         }
         self.last_reported = Some(Utc::now().timestamp());
     }
 
 }
+
+#[cfg(test)]
+mod tests {
+    use super::Context;
+    use binding::component::Component;
+    use plugin::discovery::{ComponentSpec, TemplateSpec};
+    use plugin::error::{ComponentError, ReportError};
+    use plugin::invalid_value_policy::{InvalidValuePolicy, CLAMP_LIMIT};
+    use plugin::metric_kind::MetricKind;
+    use plugin::metric_name::MetricName;
+    use plugin::recorder::Recorder;
+    use std::collections::HashSet;
+    use std::thread;
+    use std::time::Duration;
+
+    fn context() -> Context{
+        Context::new("license".to_string(), "1.0.0".to_string(), "host".to_string(), 1)
+    }
+
+    #[test]
+    fn clamped_infinities_stay_in_the_payload(){
+        let mut context = context();
+        context.invalid_value_policy = InvalidValuePolicy::Clamp;
+        let mut component = Component::new("db".to_string(), "com.example.db".to_string());
+        let handle = component.add_metric(&MetricName::parse("Component/Latency[ms]").unwrap());
+        context.register_component(component).unwrap();
+        for _ in 0..1000{
+            context.record(handle, f64::INFINITY).unwrap();
+            context.record(handle, f64::NEG_INFINITY).unwrap();
+        }
+        assert!(context.record(handle, f64::NAN).is_err());
+        assert_eq!(context.invalid_samples(handle), Ok(2001));
+
+        let hash = context.request_hash(&context.snapshot());
+        let metric = &hash["components"][0]["metrics"]["Component/Latency[ms]"];
+        assert_eq!(metric["count"], json!(2000));
+        assert_eq!(metric["total"], json!(0f64));
+        assert_eq!(metric["max"], json!(CLAMP_LIMIT));
+        assert_eq!(metric["min"], json!(-CLAMP_LIMIT));
+        let sum_of_squares = metric["sum_of_squares"].as_f64().unwrap();
+        assert!((sum_of_squares / (2000f64 * CLAMP_LIMIT * CLAMP_LIMIT) - 1f64).abs() < 1e-9);
+    }
+
+    #[test]
+    fn recorded_counter_rates_use_the_time_of_the_readings(){
+        let mut context = context();
+        let mut component = Component::new("web".to_string(), "com.example.web".to_string());
+        let requests = component.add_metric_with_kind(
+            &MetricName::parse("Component/Requests[requests/second]").unwrap(), MetricKind::counter());
+        context.register_component(component).unwrap();
+        let recorder = Recorder::new(InvalidValuePolicy::Reject);
+
+        recorder.record(requests, 50f64);
+        recorder.record(requests, 100f64);
+        for (handle, pending) in recorder.drain(){
+            context.apply_recorded(handle, pending).unwrap();
+        }
+        thread::sleep(Duration::from_millis(50));
+        recorder.record(requests, 200f64);
+        thread::sleep(Duration::from_millis(250));
+        for (handle, pending) in recorder.drain(){
+            context.apply_recorded(handle, pending).unwrap();
+        }
+
+        let snapshot = context.snapshot();
+        let rate = snapshot.components[0].metrics["Component/Requests[requests/second]"];
+        assert_eq!(rate.count, 1);
+        // 100 requests in about 50ms, not in the 300ms until the readings were applied.
+        assert!(rate.total > 1000f64 && rate.total <= 2000f64, "rate {}", rate.total);
+    }
+
+    #[test]
+    fn components_sharing_a_guid_are_told_apart_by_name(){
+        let mut context = context();
+        let redis = |name: &str| {
+            let mut component = Component::new(name.to_string(), "com.example.redis".to_string());
+            component.add_metric(&MetricName::parse("Component/Memory/Used[bytes]").unwrap());
+            component
+        };
+        context.register_component(redis("redis-6379")).unwrap();
+        context.report_metric("com.example.redis".to_string(), "Component/Memory/Used[bytes]".to_string(), 1f64).unwrap();
+        context.register_component(redis("redis-6380")).unwrap();
+        assert_eq!(context.register_component(redis("redis-6380")), Err(ComponentError::Duplicate{
+            name: "redis-6380".to_string(),
+            guid: "com.example.redis".to_string()
+        }));
+
+        assert_eq!(context.report_metric("com.example.redis".to_string(), "Component/Memory/Used[bytes]".to_string(), 2f64),
+            Err(ReportError::AmbiguousComponent("com.example.redis".to_string())));
+        assert_eq!(context.metric_handle("com.example.redis", "Component/Memory/Used[bytes]"), None);
+        context.report_component_metric("redis-6380", "com.example.redis", "Component/Memory/Used[bytes]".to_string(), 3f64).unwrap();
+        assert_eq!(context.report_component_metric("redis-6381", "com.example.redis", "Component/Memory/Used[bytes]".to_string(), 4f64),
+            Err(ReportError::UnknownNamedComponent{ name: "redis-6381".to_string(), guid: "com.example.redis".to_string() }));
+        let handle = context.component_metric_handle("redis-6379", "com.example.redis", "Component/Memory/Used[bytes]").unwrap();
+        assert_eq!(context.statistics(handle).unwrap().mean(), Some(1f64));
+        let handle = context.component_metric_handle("redis-6380", "com.example.redis", "Component/Memory/Used[bytes]").unwrap();
+        assert_eq!(context.statistics(handle).unwrap().mean(), Some(3f64));
+
+        context.unregister_component("redis-6379", "com.example.redis").unwrap();
+        assert!(context.is_registered("redis-6380", "com.example.redis"));
+        assert_eq!(context.metric_handle("com.example.redis", "Component/Memory/Used[bytes]"), Some(handle));
+    }
+
+    fn spec(name: &str) -> ComponentSpec{
+        ComponentSpec{
+            name: name.to_string(),
+            guid: "com.example.redis".to_string(),
+            templates: vec![TemplateSpec{ template: "Component/Memory/{kind}[bytes]".into(), kind: MetricKind::Gauge }]
+        }
+    }
+
+    fn registered_names(context: &Context) -> Vec<String>{
+        context.snapshot().components.into_iter().map(|component| component.name).collect()
+    }
+
+    #[test]
+    fn discovery_registers_new_and_unregisters_gone_components(){
+        let mut context = context();
+        context.register_component(Component::new("manual".to_string(), "com.example.redis".to_string())).unwrap();
+        let mut discovered = HashSet::new();
+
+        context.sync_discovered(&mut discovered, vec![spec("6379"), spec("6380")], 10);
+        assert_eq!(registered_names(&context), vec!["manual", "6379", "6380"]);
+        context.report_component_labeled("6380", "com.example.redis", "Component/Memory/{kind}[bytes]".into(),
+            &[("kind", "used")], 1024f64).unwrap();
+        let handle = context.component_metric_handle("6380", "com.example.redis", "Component/Memory/used[bytes]").unwrap();
+        assert_eq!(context.statistics(handle).unwrap().mean(), Some(1024f64));
+
+        // Unregistered by hand while still discovered: registered again on the next discovery.
+        context.unregister_component("6379", "com.example.redis").unwrap();
+        context.sync_discovered(&mut discovered, vec![spec("6379")], 10);
+        assert_eq!(registered_names(&context), vec!["manual", "6379"]);
+
+        context.sync_discovered(&mut discovered, vec![], 10);
+        assert_eq!(registered_names(&context), vec!["manual"]);
+        assert!(discovered.is_empty());
+    }
+}
@@ -0,0 +1,162 @@
+use std::collections::BTreeMap;
+
+/// Relative error of the values returned by `Histogram::percentile`.
+const RELATIVE_ACCURACY: f64 = 0.01;
+
+///
+/// Log-bucketed histogram of the samples recorded in a deliver interval. Bucket boundaries grow
+/// geometrically, so percentiles are accurate to within `RELATIVE_ACCURACY` of the true sample value
+/// regardless of magnitude, while memory only grows with the range of recorded values.
+#[derive(Debug, Clone)]
+pub struct Histogram{
+    gamma: f64,
+    positive: BTreeMap<i32, u64>,
+    negative: BTreeMap<i32, u64>,
+    zeros: u64,
+    count: u64
+}
+
+impl Histogram{
+    pub fn new() -> Self{
+        Histogram{
+            gamma: (1f64 + RELATIVE_ACCURACY) / (1f64 - RELATIVE_ACCURACY),
+            positive: BTreeMap::new(),
+            negative: BTreeMap::new(),
+            zeros: 0,
+            count: 0
+        }
+    }
+
+    fn index(&self, magnitude: f64) -> i32{
+        (magnitude.ln() / self.gamma.ln()).ceil() as i32
+    }
+
+    fn bucket_value(&self, index: i32) -> f64{
+        2f64 * self.gamma.powi(index) / (self.gamma + 1f64)
+    }
+
+    pub fn record(&mut self, value: f64){
+        self.record_count(value, 1);
+    }
+
+    fn record_count(&mut self, value: f64, count: u64){
+        if !value.is_finite() || count == 0{
+            return;
+        }
+        if value > 0f64{
+            let index = self.index(value);
+            *self.positive.entry(index).or_insert(0) += count;
+        }else if value < 0f64{
+            let index = self.index(-value);
+            *self.negative.entry(index).or_insert(0) += count;
+        }else{
+            self.zeros += count;
+        }
+        self.count += count;
+    }
+
+    /// Adds the samples of `other` with every value multiplied by `factor`, e.g. to convert units.
+    /// Scaled samples are re-bucketed from their bucket value, which adds at most another
+    /// `RELATIVE_ACCURACY` of error.
+    pub fn merge_scaled(&mut self, other: &Histogram, factor: f64){
+        for (index, count) in &other.positive{
+            self.record_count(factor * other.bucket_value(*index), *count);
+        }
+        for (index, count) in &other.negative{
+            self.record_count(-factor * other.bucket_value(*index), *count);
+        }
+        self.record_count(0f64, other.zeros);
+    }
+
+    /// Approximate value below which `percentile` percent of the recorded samples fall.
+    pub fn percentile(&self, percentile: f64) -> Option<f64>{
+        if self.count == 0{
+            return None;
+        }
+        let rank = (percentile.clamp(0f64, 100f64) / 100f64 * (self.count - 1) as f64).round() as u64;
+        let mut seen = 0;
+        for (index, count) in self.negative.iter().rev(){
+            seen += count;
+            if seen > rank{
+                return Some(-self.bucket_value(*index));
+            }
+        }
+        seen += self.zeros;
+        if seen > rank{
+            return Some(0f64);
+        }
+        for (index, count) in &self.positive{
+            seen += count;
+            if seen > rank{
+                return Some(self.bucket_value(*index));
+            }
+        }
+        None
+    }
+
+    pub fn reset(&mut self){
+        self.positive.clear();
+        self.negative.clear();
+        self.zeros = 0;
+        self.count = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Histogram, RELATIVE_ACCURACY};
+
+    fn assert_within_accuracy(actual: Option<f64>, expected: f64){
+        let actual = actual.unwrap();
+        assert!((actual - expected).abs() <= RELATIVE_ACCURACY * expected.abs(),
+            "{} is not within {} of {}", actual, RELATIVE_ACCURACY, expected);
+    }
+
+    #[test]
+    fn percentiles_are_within_relative_accuracy(){
+        let mut histogram = Histogram::new();
+        for value in 1..1001{
+            histogram.record(value as f64);
+        }
+        assert_within_accuracy(histogram.percentile(0f64), 1f64);
+        assert_within_accuracy(histogram.percentile(50f64), 501f64);
+        assert_within_accuracy(histogram.percentile(99f64), 990f64);
+        assert_within_accuracy(histogram.percentile(100f64), 1000f64);
+    }
+
+    #[test]
+    fn percentiles_cover_negative_values_and_zero(){
+        let mut histogram = Histogram::new();
+        for value in &[-100f64, -10f64, 0f64, 10f64, 100f64]{
+            histogram.record(*value);
+        }
+        assert_within_accuracy(histogram.percentile(0f64), -100f64);
+        assert_eq!(histogram.percentile(50f64), Some(0f64));
+        assert_within_accuracy(histogram.percentile(100f64), 100f64);
+    }
+
+    #[test]
+    fn merge_scaled_converts_the_merged_samples(){
+        let mut seconds = Histogram::new();
+        for value in 1..101{
+            seconds.record(value as f64 / 1000f64);
+        }
+        let mut milliseconds = Histogram::new();
+        milliseconds.record(-5f64);
+        milliseconds.merge_scaled(&seconds, 1000f64);
+        assert_within_accuracy(milliseconds.percentile(0f64), -5f64);
+        assert!((milliseconds.percentile(50f64).unwrap() - 50f64).abs() <= 2f64 * RELATIVE_ACCURACY * 50f64);
+        assert!((milliseconds.percentile(100f64).unwrap() - 100f64).abs() <= 2f64 * RELATIVE_ACCURACY * 100f64);
+    }
+
+    #[test]
+    fn reset_and_non_finite_values_leave_histogram_empty(){
+        let mut histogram = Histogram::new();
+        histogram.record(f64::NAN);
+        histogram.record(f64::INFINITY);
+        assert_eq!(histogram.percentile(50f64), None);
+        histogram.record(5f64);
+        histogram.reset();
+        assert_eq!(histogram.percentile(50f64), None);
+    }
+}
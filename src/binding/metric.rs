@@ -1,91 +1,489 @@
+use serde_json::Value;
+use chrono::prelude::*;
+use plugin::summary::{MetricSummary, SummaryError};
+use plugin::unit::Unit;
+use plugin::metric_kind::MetricKind;
+use plugin::metric_name::MetricName;
+use plugin::empty_policy::EmptyPolicy;
+use plugin::invalid_value_policy::{InvalidValuePolicy, CLAMP_LIMIT};
+use plugin::error::ReportError;
+use binding::histogram::Histogram;
+use binding::smoother::Smoother;
+use plugin::smoothing::Smoothing;
+use plugin::statistics::Statistics;
+use plugin::reset_policy::ResetPolicy;
+use plugin::attributes::Attributes;
+use plugin::recorder::Samples;
+use std::time::Instant;
 use std::fmt;
 
 #[derive(Debug, Clone)]
 pub struct Metric{
     pub name: String,
-    pub value: f64,
     pub prev: f64,
-    count: u64,
-    min: f64,
-    max: f64,
-    sum_of_squares: f64
+    pub unit: Unit,
+    pub kind: MetricKind,
+    pub empty_policy: Option<EmptyPolicy>,
+    pub reset_policy: ResetPolicy,
+    pub attributes: Attributes,
+    interval_start: i64,
+    last_value: Option<f64>,
+    invalid_samples: u64,
+    prev_at: Option<Instant>,
+    reported: bool,
+    idle_cycles: u32,
+    summary: MetricSummary,
+    statistics: Statistics,
+    histogram: Option<Histogram>,
+    percentile_names: Vec<(f64, String)>,
+    smoothers: Vec<Smoother>
 }
 
 impl fmt::Display for Metric {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "Name: {}, Value: {}, Count: {}, Min.: {}, Max.: {}, Sum of sqaures: {}",
-            self.name, self.value, self.count, self.min, self.max, self.sum_of_squares)
+        write!(f, "Name: {}, {}", self.name, self.summary)
     }
 }
 
 impl Metric{
 
-    pub fn new(name: String) -> Self{
+    pub fn new(name: &MetricName, kind: MetricKind) -> Self{
+        let mut histogram = None;
+        let mut percentile_names = vec![];
+        if let MetricKind::Histogram{ ref percentiles } = kind{
+            histogram = Some(Histogram::new());
+            for percentile in percentiles{
+                match name.child(&format!("p{}", percentile)){
+                    Ok(percentile_name) => percentile_names.push((*percentile, percentile_name.to_string())),
+                    Err(e) => error!(target: "agent", "Skipping p{} of metric {}. Error: {}", percentile, name, e)
+                }
+            }
+        }
         Metric{
-            name: name,
-            value: 0f64,
+            name: name.to_string(),
             prev: 0f64,
-            count: 0,
-            min: 0f64,
-            max: 0f64,
-            sum_of_squares: 0f64
-        }
-    }
-
-    pub fn new_valued(name: String, value: f64,
-        options: Option<(u64, f64, f64, f64)>) -> Self{
-        if options.is_some(){
-            let options = options.unwrap();
-            Metric{
-                name: name,
-                value: value,
-                prev: 0f64,
-                count: options.0,
-                min: options.1,
-                max: options.2,
-                sum_of_squares: options.3
+            unit: Unit::parse(name.unit()),
+            kind,
+            empty_policy: None,
+            reset_policy: ResetPolicy::OnDeliver,
+            attributes: Attributes::new(),
+            interval_start: Utc::now().timestamp(),
+            last_value: None,
+            invalid_samples: 0,
+            prev_at: None,
+            reported: false,
+            idle_cycles: 0,
+            summary: MetricSummary::empty(),
+            statistics: Statistics::default(),
+            histogram,
+            percentile_names,
+            smoothers: vec![]
+        }
+    }
+
+    pub fn summary(&self) -> &MetricSummary{
+        &self.summary
+    }
+
+    /// Mean and variance of the samples recorded in the current interval.
+    pub fn statistics(&self) -> Statistics{
+        self.statistics
+    }
+
+    /// Number of NaN or infinite samples reported to this metric since it was created.
+    pub fn invalid_samples(&self) -> u64{
+        self.invalid_samples
+    }
+
+    /// Applies `policy` to a reported value, returning the value to record.
+    pub fn check_value(&mut self, value: f64, policy: InvalidValuePolicy) -> Result<f64, ReportError>{
+        if value.is_finite(){
+            return Ok(value);
+        }
+        self.invalid_samples += 1;
+        warn!(target: "agent", "Invalid value {} reported to metric {} ({} so far).",
+            value, self.name, self.invalid_samples);
+        match policy{
+            InvalidValuePolicy::Clamp if !value.is_nan() => Ok(value.clamp(-CLAMP_LIMIT, CLAMP_LIMIT)),
+            _ => Err(ReportError::InvalidValue{ metric: self.name.clone(), value })
+        }
+    }
+
+    pub fn check_summary(&mut self, summary: &MetricSummary) -> Result<(), ReportError>{
+        // A summary carries no readings, so the increase of a counter cannot be derived from it.
+        if let MetricKind::Counter{ .. } = self.kind{
+            return Err(ReportError::SummaryOnCounter(self.name.clone()));
+        }
+        // Nor does it carry the distribution, so percentiles would leave its samples out.
+        if let MetricKind::Histogram{ .. } = self.kind{
+            return Err(ReportError::SummaryOnHistogram(self.name.clone()));
+        }
+        if let Err(e) = summary.validate(){
+            if e == SummaryError::NonFinite{
+                self.invalid_samples += summary.count;
+                warn!(target: "agent", "Invalid summary {} reported to metric {} ({} invalid samples so far).",
+                    summary, self.name, self.invalid_samples);
+            }
+            return Err(ReportError::from(e));
+        }
+        Ok(())
+    }
+
+    /// Adds a companion metric smoothing this metric's values. Adding the same smoothing twice has
+    /// no effect; a different smoothing with the same companion name, such as a second `Ewma` with
+    /// another alpha, is rejected.
+    pub fn add_smoothing(&mut self, smoothing: Smoothing) -> Result<(), ReportError>{
+        if !smoothing.is_valid(){
+            return Err(ReportError::InvalidSmoothing(smoothing));
+        }
+        if self.smoothers.iter().any(|smoother| smoother.smoothing == smoothing){
+            return Ok(());
+        }
+        let name = MetricName::parse(&self.name).and_then(|name| name.child(&smoothing.to_string()))?.to_string();
+        if self.smoothers.iter().any(|smoother| smoother.name == name){
+            return Err(ReportError::ConflictingSmoothing{ metric: self.name.clone(), smoothing });
+        }
+        self.smoothers.push(Smoother::new(smoothing, name));
+        Ok(())
+    }
+
+    pub fn record(&mut self, value: f64) -> f64{
+        self.reported = true;
+        match self.kind{
+            MetricKind::Timeslice | MetricKind::Gauge => self.aggregate(&MetricSummary::from_value(value)),
+            MetricKind::Counter{ wraps_at } => self.record_counter(value, wraps_at),
+            MetricKind::Histogram{ .. } => {
+                if let Some(ref mut histogram) = self.histogram{
+                    histogram.record(value);
+                }
+                self.aggregate(&MetricSummary::from_value(value))
             }
+        }
+    }
+
+    fn record_counter(&mut self, value: f64, wraps_at: Option<f64>) -> f64{
+        self.record_counter_at(value, wraps_at, Instant::now())
+    }
+
+    /// Records a counter reading taken at `now`. Readings older than the previous one are dropped.
+    fn record_counter_at(&mut self, value: f64, wraps_at: Option<f64>, now: Instant) -> f64{
+        let prev = self.prev;
+        if let Some(prev_at) = self.prev_at{
+            if now < prev_at{
+                warn!(target: "agent", "Dropping reading {} of counter {}, it is older than the previous reading.",
+                    value, self.name);
+                return prev;
+            }
+            let delta = if value >= prev{
+                value - prev
+            }else{
+                match wraps_at{
+                    Some(wraps_at) => wraps_at - prev + value,
+                    None => value
+                }
+            };
+            let elapsed = now.duration_since(prev_at).as_secs_f64();
+            if let Some(value) = self.counter_value(delta, elapsed){
+                self.merge(&MetricSummary::from_value(value));
+            }
+        }
+        self.prev = value;
+        self.prev_at = Some(now);
+        prev
+    }
+
+    fn counter_value(&self, delta: f64, elapsed: f64) -> Option<f64>{
+        match self.unit{
+            Unit::PerSecond(ref what) | Unit::PerMinute(ref what) => {
+                if elapsed <= 0f64{
+                    return None;
+                }
+                Unit::PerSecond(what.clone()).convert(delta / elapsed, &self.unit).ok()
+            },
+            _ => Some(delta)
+        }
+    }
+
+    /// Records values aggregated by a `Recorder`. Counters only take the latest reading.
+    pub(crate) fn record_samples(&mut self, samples: &Samples){
+        if samples.summary.is_empty(){
+            return;
+        }
+        self.reported = true;
+        match self.kind{
+            MetricKind::Counter{ wraps_at } => {
+                if let Some((value, at)) = samples.last{
+                    self.record_counter_at(value, wraps_at, at);
+                }
+            },
+            MetricKind::Histogram{ .. } => {
+                if let Some(ref mut histogram) = self.histogram{
+                    histogram.merge_scaled(&samples.histogram, 1f64);
+                }
+                self.aggregate(&samples.summary);
+            },
+            MetricKind::Timeslice | MetricKind::Gauge => {
+                self.aggregate(&samples.summary);
+            }
+        }
+    }
+
+    /// Counts samples that were dropped as invalid before reaching the metric.
+    pub fn add_invalid_samples(&mut self, count: u64){
+        if count > 0{
+            self.invalid_samples += count;
+            warn!(target: "agent", "{} invalid values recorded to metric {} ({} so far).",
+                count, self.name, self.invalid_samples);
+        }
+    }
+
+    pub fn aggregate(&mut self, summary: &MetricSummary) -> f64{
+        self.reported = true;
+        let prev = self.prev;
+        self.prev = summary.total;
+        self.merge(summary);
+        prev
+    }
+
+    fn merge(&mut self, summary: &MetricSummary){
+        if summary.count == 1{
+            self.statistics.record(summary.total);
         }else{
-            Metric{
-                name: name,
-                value: value,
-                prev: 0f64,
-                count: 1,
-                min: value,
-                max: value,
-                sum_of_squares: value * value
+            self.statistics.merge(&Statistics::from_summary(summary));
+        }
+        if !summary.is_empty(){
+            let value = summary.total / summary.count as f64;
+            self.last_value = Some(value);
+            for smoother in &mut self.smoothers{
+                smoother.record(value);
             }
         }
+        self.summary.merge(summary);
+    }
+
+    /// Whether the metric was reported within the last `ttl` deliver cycles. A `ttl` of 0 never expires.
+    pub fn is_live(&self, ttl: u32) -> bool{
+        ttl == 0 || self.reported || self.idle_cycles < ttl
     }
 
-    pub fn aggregate(&mut self, metric: &Metric) -> f64{
-        let prev = self.prev.clone();
-        self.prev = metric.value;
-        self.value += metric.value;
-        if self.count == 0{
-            self.min = metric.value;
+    /// Policy for empty intervals: the metric's own, else repeating for gauges and `default` otherwise.
+    pub fn effective_empty_policy(&self, default: EmptyPolicy) -> EmptyPolicy{
+        match (self.empty_policy, &self.kind){
+            (Some(policy), _) => policy,
+            (None, &MetricKind::Gauge) => EmptyPolicy::RepeatLast,
+            (None, _) => default
+        }
+    }
+
+    /// Summary delivered for the current interval, if any.
+    pub fn interval_summary(&self, default: EmptyPolicy) -> Option<MetricSummary>{
+        if !self.summary.is_empty(){
+            return Some(self.summary);
+        }
+        match self.effective_empty_policy(default){
+            EmptyPolicy::Omit => None,
+            EmptyPolicy::Zeros => Some(MetricSummary::empty()),
+            EmptyPolicy::RepeatLast => self.last_value.map(MetricSummary::from_value)
+        }
+    }
+
+    /// Summaries delivered for the current interval: the metric's own followed by its percentile
+    /// and smoothing companions.
+    pub fn summaries(&self, default: EmptyPolicy) -> Vec<(String, MetricSummary)>{
+        let mut summaries = vec![];
+        if let Some(summary) = self.interval_summary(default){
+            summaries.push((self.name.clone(), summary));
+        }
+        if let Some(ref histogram) = self.histogram{
+            for &(percentile, ref name) in &self.percentile_names{
+                if let Some(mut value) = histogram.percentile(percentile){
+                    if self.summary.min <= self.summary.max{
+                        value = value.clamp(self.summary.min, self.summary.max);
+                    }
+                    summaries.push((name.clone(), MetricSummary::from_value(value)));
+                }
+            }
+        }
+        for smoother in &self.smoothers{
+            if let Some(value) = smoother.value(){
+                summaries.push((smoother.name.clone(), MetricSummary::from_value(value)));
+            }
+        }
+        summaries
+    }
+
+    /// Unix timestamp since which the metric has been aggregating, i.e. of its creation or last reset.
+    pub fn interval_start(&self) -> i64{
+        self.interval_start
+    }
+
+    /// Called after each successful delivery at `now`. Clears the aggregated values if the reset
+    /// policy says so.
+    pub fn delivered(&mut self, now: i64){
+        if self.reported{
+            self.idle_cycles = 0;
         }else{
-            self.min = self.min.min(metric.min);
+            self.idle_cycles = self.idle_cycles.saturating_add(1);
         }
-        self.count += metric.count;
-        self.max = self.min.max(metric.max);
-        self.sum_of_squares += metric.sum_of_squares;
-        prev
+        self.reported = false;
+        let reset = match self.reset_policy{
+            ResetPolicy::OnDeliver => true,
+            ResetPolicy::Never => false,
+            ResetPolicy::Every(period) => now - self.interval_start >= period.as_secs() as i64
+        };
+        if reset{
+            self.reset(now);
+        }
+    }
+
+    fn reset(&mut self, now: i64){
+        self.interval_start = now;
+        self.summary = MetricSummary::empty();
+        self.statistics = Statistics::default();
+        // Counters keep their last reading in `prev` and gauges their `last_value` across intervals.
+        if let Some(ref mut histogram) = self.histogram{
+            histogram.reset();
+        }
+    }
+}
+
+pub fn summary_hash(summary: &MetricSummary) -> Value{
+    json!({
+        "total": summary.total,
+        "count": summary.count,
+        "min": summary.min,
+        "max": summary.max,
+        "sum_of_squares": summary.sum_of_squares
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Metric;
+    use plugin::empty_policy::EmptyPolicy;
+    use plugin::error::ReportError;
+    use plugin::metric_kind::MetricKind;
+    use plugin::metric_name::MetricName;
+    use plugin::summary::MetricSummary;
+    use plugin::smoothing::Smoothing;
+
+    fn metric(name: &str, kind: MetricKind) -> Metric{
+        Metric::new(&MetricName::parse(name).unwrap(), kind)
+    }
+
+    fn interval_summary(metric: &Metric) -> Option<MetricSummary>{
+        metric.interval_summary(EmptyPolicy::Omit)
+    }
+
+    #[test]
+    fn counter_records_increases_between_readings(){
+        let mut counter = metric("Component/Requests[requests]", MetricKind::counter());
+        counter.record(100f64);
+        assert_eq!(interval_summary(&counter), None);
+        counter.record(130f64);
+        counter.record(150f64);
+        assert_eq!(interval_summary(&counter), Some(MetricSummary::from_samples(&[30.0, 20.0]).unwrap()));
+    }
+
+    #[test]
+    fn counter_handles_wraparound_and_reset(){
+        let mut wrapping = metric("Component/Bytes[bytes]", MetricKind::wrapping_counter(256f64));
+        wrapping.record(250f64);
+        wrapping.record(4f64);
+        assert_eq!(interval_summary(&wrapping), Some(MetricSummary::from_value(10.0)));
+
+        let mut resetting = metric("Component/Bytes[bytes]", MetricKind::counter());
+        resetting.record(250f64);
+        resetting.record(4f64);
+        assert_eq!(interval_summary(&resetting), Some(MetricSummary::from_value(4.0)));
+    }
+
+    #[test]
+    fn histogram_sends_percentile_companions_clamped_to_the_interval(){
+        let mut latency = metric("Component/Latency[ms]", MetricKind::histogram(&[50.0, 100.0]));
+        for value in 1..101{
+            latency.record(value as f64);
+        }
+        let summaries = latency.summaries(EmptyPolicy::Omit);
+        let names: Vec<&str> = summaries.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, vec!["Component/Latency[ms]", "Component/Latency/p50[ms]", "Component/Latency/p100[ms]"]);
+        assert_eq!(summaries[0].1, MetricSummary::from_samples(&(1..101).map(f64::from).collect::<Vec<f64>>()).unwrap());
+        assert!((summaries[1].1.total - 50f64).abs() <= 1f64);
+        assert_eq!(summaries[2].1, MetricSummary::from_value(100f64));
+
+        latency.delivered(0);
+        assert!(latency.summaries(EmptyPolicy::Omit).is_empty());
+    }
+
+    #[test]
+    fn gauge_and_counter_carry_state_across_deliveries(){
+        let mut gauge = metric("Component/Queue/Depth[messages]", MetricKind::Gauge);
+        gauge.record(7f64);
+        gauge.delivered(0);
+        assert_eq!(interval_summary(&gauge), Some(MetricSummary::from_value(7.0)));
+
+        let mut counter = metric("Component/Requests[requests]", MetricKind::counter());
+        counter.record(100f64);
+        counter.delivered(0);
+        counter.record(120f64);
+        assert_eq!(interval_summary(&counter), Some(MetricSummary::from_value(20.0)));
+    }
+
+    #[test]
+    fn empty_intervals_follow_the_empty_policy(){
+        let mut latency = metric("Component/Latency[ms]", MetricKind::Timeslice);
+        latency.record(12f64);
+        latency.delivered(0);
+        assert_eq!(latency.interval_summary(EmptyPolicy::Omit), None);
+        assert_eq!(latency.interval_summary(EmptyPolicy::Zeros), Some(MetricSummary::empty()));
+        assert_eq!(latency.interval_summary(EmptyPolicy::RepeatLast), Some(MetricSummary::from_value(12.0)));
+
+        latency.empty_policy = Some(EmptyPolicy::Zeros);
+        assert_eq!(latency.summaries(EmptyPolicy::Omit),
+            vec![("Component/Latency[ms]".to_string(), MetricSummary::empty())]);
+
+        let mut gauge = metric("Component/Queue/Depth[messages]", MetricKind::Gauge);
+        assert_eq!(gauge.interval_summary(EmptyPolicy::Zeros), None);
+        gauge.empty_policy = Some(EmptyPolicy::Omit);
+        gauge.record(7f64);
+        gauge.delivered(0);
+        assert_eq!(gauge.interval_summary(EmptyPolicy::RepeatLast), None);
+    }
+
+    #[test]
+    fn counter_rejects_summaries(){
+        let mut counter = metric("Component/Requests[requests]", MetricKind::counter());
+        assert_eq!(counter.check_summary(&MetricSummary::from_value(5.0)),
+            Err(ReportError::SummaryOnCounter("Component/Requests[requests]".into())));
     }
 
-    pub fn to_hash(&self) -> (String, Vec<f64>){
-        (
-            self.name.clone(),
-            vec![self.value, self.count as f64, self.min, self.max, self.sum_of_squares]
-        )
+    #[test]
+    fn smoothing_is_kept_across_deliveries_and_conflicts_are_rejected(){
+        let mut connections = metric("Component/Connections[connections]", MetricKind::Gauge);
+        connections.add_smoothing(Smoothing::Ewma{ alpha: 0.5 }).unwrap();
+        assert_eq!(connections.add_smoothing(Smoothing::Ewma{ alpha: 0.5 }), Ok(()));
+        assert_eq!(connections.add_smoothing(Smoothing::Ewma{ alpha: 0.7 }), Err(ReportError::ConflictingSmoothing{
+            metric: "Component/Connections[connections]".into(),
+            smoothing: Smoothing::Ewma{ alpha: 0.7 }
+        }));
+        connections.record(10f64);
+        connections.delivered(0);
+        connections.record(20f64);
+        assert_eq!(connections.summaries(EmptyPolicy::Omit), vec![
+            ("Component/Connections[connections]".to_string(), MetricSummary::from_value(20f64)),
+            ("Component/Connections/ewma[connections]".to_string(), MetricSummary::from_value(15f64))
+        ]);
     }
 
-    pub fn reset(&mut self){
-        // self.prev = self.value;
-        self.value = 0f64;
-        self.count = 0;
-        self.min = 0f64;
-        self.max = 0f64;
-        self.sum_of_squares = 0f64;
+    #[test]
+    fn histogram_rejects_summaries(){
+        let mut latency = metric("Component/Latency[ms]", MetricKind::histogram(&[50.0]));
+        assert_eq!(latency.check_summary(&MetricSummary::from_value(5.0)),
+            Err(ReportError::SummaryOnHistogram("Component/Latency[ms]".into())));
+        latency.record(3f64);
+        assert_eq!(latency.summaries(EmptyPolicy::Omit), vec![
+            ("Component/Latency[ms]".to_string(), MetricSummary::from_value(3f64)),
+            ("Component/Latency/p50[ms]".to_string(), MetricSummary::from_value(3f64))
+        ]);
     }
 }
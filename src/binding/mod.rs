@@ -1,6 +1,8 @@
 pub mod component;
 pub mod config;
 pub mod metric;
+pub mod histogram;
+pub mod smoother;
 pub mod context;
 pub mod request;
 pub mod connection;
\ No newline at end of file
@@ -11,8 +11,8 @@ pub struct Request{
 impl Request{
     pub fn new(data: Value, license_key: String) -> Self{
         Request{
-            data: data,
-            license_key: license_key,
+            data,
+            license_key,
             delivered: false
         }
     }
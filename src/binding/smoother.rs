@@ -0,0 +1,81 @@
+use plugin::smoothing::Smoothing;
+use std::collections::VecDeque;
+
+///
+/// State of one `Smoothing` of a metric. Unlike the interval summary it is not reset on delivery.
+#[derive(Debug, Clone)]
+pub struct Smoother{
+    pub smoothing: Smoothing,
+    pub name: String,
+    ewma: Option<f64>,
+    window: VecDeque<f64>
+}
+
+impl Smoother{
+    pub fn new(smoothing: Smoothing, name: String) -> Self{
+        Smoother{
+            smoothing,
+            name,
+            ewma: None,
+            window: VecDeque::new()
+        }
+    }
+
+    pub fn record(&mut self, value: f64){
+        match self.smoothing{
+            Smoothing::Ewma{ alpha } => {
+                self.ewma = Some(match self.ewma{
+                    Some(ewma) => alpha * value + (1f64 - alpha) * ewma,
+                    None => value
+                });
+            },
+            Smoothing::SlidingAverage{ window } | Smoothing::SlidingMax{ window } => {
+                if self.window.len() == window{
+                    self.window.pop_front();
+                }
+                self.window.push_back(value);
+            }
+        }
+    }
+
+    pub fn value(&self) -> Option<f64>{
+        match self.smoothing{
+            Smoothing::Ewma{ .. } => self.ewma,
+            _ if self.window.is_empty() => None,
+            Smoothing::SlidingAverage{ .. } => Some(self.window.iter().sum::<f64>() / self.window.len() as f64),
+            Smoothing::SlidingMax{ .. } => self.window.iter().cloned().fold(None, |max: Option<f64>, value|
+                Some(max.map_or(value, |max| max.max(value))))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Smoother;
+    use plugin::smoothing::Smoothing;
+
+    fn smoother(smoothing: Smoothing, values: &[f64]) -> Smoother{
+        let mut smoother = Smoother::new(smoothing, "Component/Connections/smoothed[connections]".into());
+        for &value in values{
+            smoother.record(value);
+        }
+        smoother
+    }
+
+    #[test]
+    fn ewma_starts_at_the_first_value_and_weighs_new_values_by_alpha(){
+        assert_eq!(smoother(Smoothing::Ewma{ alpha: 0.5 }, &[]).value(), None);
+        assert_eq!(smoother(Smoothing::Ewma{ alpha: 0.5 }, &[10f64]).value(), Some(10f64));
+        assert_eq!(smoother(Smoothing::Ewma{ alpha: 0.5 }, &[10f64, 20f64]).value(), Some(15f64));
+        assert_eq!(smoother(Smoothing::Ewma{ alpha: 0.25 }, &[10f64, 20f64, 0f64]).value(), Some(9.375));
+        assert_eq!(smoother(Smoothing::Ewma{ alpha: 1f64 }, &[10f64, 20f64, 0f64]).value(), Some(0f64));
+    }
+
+    #[test]
+    fn sliding_windows_evict_the_oldest_values(){
+        assert_eq!(smoother(Smoothing::SlidingAverage{ window: 2 }, &[]).value(), None);
+        assert_eq!(smoother(Smoothing::SlidingAverage{ window: 2 }, &[1f64, 5f64, 3f64]).value(), Some(4f64));
+        assert_eq!(smoother(Smoothing::SlidingMax{ window: 2 }, &[9f64, 5f64, 3f64]).value(), Some(5f64));
+        assert_eq!(smoother(Smoothing::SlidingMax{ window: 3 }, &[9f64, 5f64, 3f64]).value(), Some(9f64));
+    }
+}
@@ -3,9 +3,28 @@ use chrono::prelude::*;
 use binding::context::Context;
 use binding::component::Component;
 use binding::config::Config;
+use plugin::summary::MetricSummary;
+use plugin::metric_name::{MetricName, MetricNameError};
+use plugin::metric_template::{MetricTemplate, TemplateError};
+use plugin::unit::Quantity;
+use plugin::error::{ReportError, ComponentError};
+use plugin::metric_kind::MetricKind;
+use plugin::timer::Timer;
+use plugin::metric_handle::MetricHandle;
+use plugin::empty_policy::EmptyPolicy;
+use plugin::rollup::{Rollup, RollupRule};
+use plugin::expression::{Expression, ExpressionError};
+use plugin::smoothing::Smoothing;
+use plugin::recorder::Recorder;
+use plugin::snapshot::Snapshot;
+use plugin::statistics::Statistics;
+use plugin::reset_policy::ResetPolicy;
+use plugin::attributes::validate_attribute;
+use plugin::discovery::ComponentDiscovery;
+use std::collections::HashSet;
+use std::mem;
 use std::time::Duration;
 use std::thread;
-use std::i64::MAX;
 use std::fmt;
 
 
@@ -15,26 +34,27 @@ use std::fmt;
 /// and are used for setting up plugins' components and metrics and reporting metrics to NewRelic API.
 /// 
 /// # Examples
-/// ```
+/// ```no_run
+/// # use newrelic_plugin::plugin::agent::Agent;
 /// // Plugin agent setup
 /// let mut agent = Agent::new("<license_key>".into(), "1.0.0".into(), "host".into(), 1234);
 /// let mut c1 = agent.create_component("Test Plugin".into(), "com.test_plugin.plugin_name".into());
-/// agent.create_metric(&mut c1, "Component/Request/Rate/host1[requests/second]".into());
-/// agent.create_metric(&mut c1, "Component/Request/Rate/host2[requests/second]".into());
-/// agent.register_component(c1);
+/// agent.create_metric(&mut c1, "Component/Request/Rate/host1[requests/second]".into()).unwrap();
+/// agent.create_metric(&mut c1, "Component/Request/Rate/host2[requests/second]".into()).unwrap();
+/// agent.register_component(c1).unwrap();
 /// 
 /// // Poll cycle function. This function is excuted every [poll_cycle] seconds.
-/// fn cycle(agent: &mut Agent){
+/// fn cycle(agent: &mut Agent<()>){
 ///     agent.report_metric(
 ///         "com.test_plugin.plugin_name".into(),
-///         "Component/Request/Rate/host1[requests/second]".into(), 
-///         (1000) as f64, None
-///     );
+///         "Component/Request/Rate/host1[requests/second]".into(),
+///         (1000) as f64
+///     ).unwrap();
 ///     agent.report_metric(
 ///         "com.test_plugin.plugin_name".into(),
-///         "Component/Request/Rate/host2[requests/second]".into(), 
-///         (1000) as f64, None
-///     );
+///         "Component/Request/Rate/host2[requests/second]".into(),
+///         (1000) as f64
+///     ).unwrap();
 /// }
 /// // Start the agent
 /// agent.run(cycle);
@@ -43,7 +63,8 @@ use std::fmt;
 /// # state
 /// An agent has the option to have a state to be able to preserve metric readings through cycles. States are passed to *Agent* instances via the ```set_state``` function. States are generic types, which means you can create a custom struct like in this example:
 /// 
-/// ```
+/// ```no_run
+/// # use newrelic_plugin::plugin::agent::Agent;
 /// struct State{
 ///     prev_file_size: i32
 /// }
@@ -51,19 +72,19 @@ use std::fmt;
 /// // Plugin agent setup
 /// let mut agent = Agent::new("<license_key>".into(), "1.0.0".into(), "host".into(), 1234);
 /// let mut c1 = agent.create_component("Test Plugin".into(), "com.test_plugin.plugin_name".into());
-/// agent.create_metric(&mut c1, "Component/File/Size/host1[bytes]".into());
-/// agent.register_component(c1);
+/// agent.create_metric(&mut c1, "Component/File/Size/host1[bytes]".into()).unwrap();
+/// agent.register_component(c1).unwrap();
 /// 
 /// // Poll cycle function. This function is excuted every [poll_cycle] seconds.
-/// fn cycle(agent: &mut Agent){
-///     let prev_size = agent.get_state().as_ref().unwrap().prev_file_size.clone();
+/// fn cycle(agent: &mut Agent<State>){
+///     let prev_size = agent.get_state().as_ref().map_or(0, |state| state.prev_file_size);
 ///     let new_size = 2000;
 ///     if prev_size != 0{
 ///         agent.report_metric(
 ///             "com.test_plugin.plugin_name".into(),
-///             "Component/File/Size/host1[bytes]".into(), 
-///             new_size - prev_size as f64, None
-///         );  
+///             "Component/File/Size/host1[bytes]".into(),
+///             (new_size - prev_size) as f64
+///         ).unwrap();
 ///     }
 ///     agent.set_state(State{prev_file_size: new_size});
 /// }
@@ -71,6 +92,333 @@ use std::fmt;
 /// agent.run(cycle);
 /// ```
 /// 
+/// # gauges
+/// Metrics are timeslices by default: each deliver interval only contains the values reported in it. Levels such as a queue depth should be created with ```MetricKind::Gauge``` instead, so that the last reported value is delivered again when the poll cycle did not report one:
+/// 
+/// ```no_run
+/// # use newrelic_plugin::plugin::agent::Agent;
+/// use newrelic_plugin::plugin::metric_kind::MetricKind;
+/// 
+/// # let agent: Agent<()> = Agent::new("<license_key>".into(), "1.0.0".into(), "host".into(), 1234);
+/// # let mut c1 = agent.create_component("Test Plugin".into(), "com.test_plugin.plugin_name".into());
+/// agent.create_metric_with_kind(&mut c1, "Component/Queue/Depth/jobs[messages]".into(),
+///     MetricKind::Gauge).unwrap();
+/// ```
+/// 
+/// # counters
+/// Instead of keeping the previous reading in the agent state, a metric can be created as a counter. The plugin then reports the raw, monotonically increasing value and the SDK records the increase since the previous poll. For rate units such as ```[requests/second]``` the increase is divided by the real time elapsed between the two polls. The first reading after startup is only used as a baseline, and counter resets (or wraparounds for ```MetricKind::wrapping_counter```) are handled. Summaries carry no readings, so reporting one to a counter fails with ```ReportError::SummaryOnCounter```:
+/// 
+/// ```no_run
+/// # use newrelic_plugin::plugin::agent::Agent;
+/// use newrelic_plugin::plugin::metric_kind::MetricKind;
+/// 
+/// # let mut agent: Agent<()> = Agent::new("<license_key>".into(), "1.0.0".into(), "host".into(), 1234);
+/// let mut c1 = agent.create_component("Test Plugin".into(), "com.test_plugin.plugin_name".into());
+/// agent.create_metric_with_kind(&mut c1, "Component/Request/Rate/host1[requests/second]".into(),
+///     MetricKind::counter()).unwrap();
+/// agent.register_component(c1).unwrap();
+/// 
+/// fn cycle(agent: &mut Agent<()>){
+///     let total_requests = 1_234_567;
+///     agent.report_metric(
+///         "com.test_plugin.plugin_name".into(),
+///         "Component/Request/Rate/host1[requests/second]".into(),
+///         total_requests as f64
+///     ).unwrap();
+/// }
+/// agent.run(cycle);
+/// ```
+/// 
+/// # histograms
+/// Metrics created with ```MetricKind::histogram``` keep the distribution of the values reported in each deliver interval. Besides the usual summary, every requested percentile is delivered as a companion metric named after it (```Component/Request/Latency/host1/p99[ms]``` for ```p99```). Percentiles are accurate to within 1% of the true value. Summaries carry no distribution, so reporting one to a histogram fails with ```ReportError::SummaryOnHistogram```:
+/// 
+/// ```no_run
+/// # use newrelic_plugin::plugin::agent::Agent;
+/// use newrelic_plugin::plugin::metric_kind::MetricKind;
+/// 
+/// # let agent: Agent<()> = Agent::new("<license_key>".into(), "1.0.0".into(), "host".into(), 1234);
+/// # let mut c1 = agent.create_component("Test Plugin".into(), "com.test_plugin.plugin_name".into());
+/// agent.create_metric_with_kind(&mut c1, "Component/Request/Latency/host1[ms]".into(),
+///     MetricKind::histogram(&[50.0, 95.0, 99.0])).unwrap();
+/// ```
+/// 
+/// # smoothing
+/// Noisy polled values, such as instantaneous connection counts, can get smoothed companion metrics. ```Smoothing::Ewma``` keeps an exponentially weighted moving average, ```Smoothing::SlidingAverage``` and ```Smoothing::SlidingMax``` the average and maximum of the last N polls. The state is kept across deliveries and sent as a child of the metric (```ewma```, ```avg<N>```, ```max<N>```):
+/// 
+/// ```no_run
+/// # use newrelic_plugin::plugin::agent::Agent;
+/// use newrelic_plugin::plugin::metric_kind::MetricKind;
+/// use newrelic_plugin::plugin::smoothing::Smoothing;
+/// 
+/// # let mut agent: Agent<()> = Agent::new("<license_key>".into(), "1.0.0".into(), "host".into(), 1234);
+/// let mut c1 = agent.create_component("Test Plugin".into(), "com.test_plugin.plugin_name".into());
+/// let connections = agent.create_metric_with_kind(&mut c1,
+///     "Component/Connections[connections]".into(), MetricKind::Gauge).unwrap();
+/// agent.register_component(c1).unwrap();
+/// // sends Component/Connections/ewma[connections] and Component/Connections/max9[connections]
+/// agent.add_smoothing(connections, Smoothing::Ewma{ alpha: 0.3 }).unwrap();
+/// agent.add_smoothing(connections, Smoothing::SlidingMax{ window: 9 }).unwrap();
+/// ```
+/// 
+/// # cumulative metrics
+/// By default every metric starts from scratch after each successful delivery. Lifetime totals or configuration values can keep aggregating with ```ResetPolicy::Never```, or be cleared on a custom schedule with ```ResetPolicy::Every```. The ```duration``` reported for a component is still the time since its last delivery, so the values of cumulative metrics cover a longer span than the duration they are sent with:
+/// 
+/// ```no_run
+/// # use newrelic_plugin::plugin::agent::Agent;
+/// use newrelic_plugin::plugin::reset_policy::ResetPolicy;
+/// use std::time::Duration;
+/// 
+/// # let mut agent: Agent<()> = Agent::new("<license_key>".into(), "1.0.0".into(), "host".into(), 1234);
+/// let mut c1 = agent.create_component("Test Plugin".into(), "com.test_plugin.plugin_name".into());
+/// let served = agent.create_metric(&mut c1, "Component/Request/Served[requests]".into()).unwrap();
+/// let errors = agent.create_metric(&mut c1, "Component/Request/Errors[requests]".into()).unwrap();
+/// agent.register_component(c1).unwrap();
+/// agent.set_reset_policy(served, ResetPolicy::Never).unwrap();
+/// agent.set_reset_policy(errors, ResetPolicy::Every(Duration::from_secs(3600))).unwrap();
+/// ```
+/// 
+/// # metric names
+/// Metric names must follow the Plugin API format ```Component/<category>/...[<unit>]```. ```create_metric``` validates the name and returns a ```MetricNameError``` if the prefix or unit is missing, a segment is empty or contains reserved characters, or the name is longer than 255 characters. Names can also be assembled with a builder:
+/// 
+/// ```no_run
+/// # use newrelic_plugin::plugin::agent::Agent;
+/// use newrelic_plugin::plugin::metric_name::MetricName;
+/// 
+/// # let agent: Agent<()> = Agent::new("<license_key>".into(), "1.0.0".into(), "host".into(), 1234);
+/// # let mut c1 = agent.create_component("Test Plugin".into(), "com.test_plugin.plugin_name".into());
+/// let name = MetricName::builder()
+///     .segment("Request").segment("Rate").segment("host1")
+///     .unit("requests/second")
+///     .build().unwrap();
+/// agent.create_metric(&mut c1, name.to_string()).unwrap();
+/// ```
+/// 
+/// # metric handles
+/// ```create_metric``` returns a copyable ```MetricHandle```. Recording through a handle with ```record```, ```record_summary``` or ```record_quantity``` skips the component and metric lookups of the ```report_*``` functions. Handles for existing metrics can be looked up with ```metric_handle```:
+/// 
+/// ```no_run
+/// # use newrelic_plugin::plugin::agent::Agent;
+/// # let mut agent: Agent<()> = Agent::new("<license_key>".into(), "1.0.0".into(), "host".into(), 1234);
+/// let mut c1 = agent.create_component("Test Plugin".into(), "com.test_plugin.plugin_name".into());
+/// let requests = agent.create_metric(&mut c1, "Component/Request/Rate/host1[requests/second]".into()).unwrap();
+/// agent.register_component(c1).unwrap();
+/// 
+/// agent.run(move |agent| {
+///     agent.record(requests, 1000f64).unwrap();
+/// });
+/// ```
+/// 
+/// # metric templates
+/// When the set of monitored hosts or queues is not known up front, metrics can be declared as templates with ```{label}``` placeholders. Concrete metrics are created on the first report for each combination of label values.
+/// 
+/// To protect the payload from a runaway number of metric names, each component creates at most ```component_metric_limit``` metrics while reporting (from templates or in auto-create mode), and the whole agent at most ```agent_metric_limit``` (see config). Once a limit is reached, reports for new names are folded into an overflow metric with the labels replaced by ```Other``` (```Component/Queue/Other/Depth[messages]```), or the last segment replaced for auto-created metrics. Counter templates are not folded, since readings of different counters make no sense as one counter; their reports return ```ReportError::CardinalityLimit``` and are dropped. The number of distinct names dropped in each interval is reported as ```Component/Supportability/MetricNames/Dropped[names]```:
+/// 
+/// ```no_run
+/// # use newrelic_plugin::plugin::agent::Agent;
+/// use newrelic_plugin::plugin::metric_kind::MetricKind;
+/// 
+/// # let mut agent: Agent<()> = Agent::new("<license_key>".into(), "1.0.0".into(), "host".into(), 1234);
+/// let mut c1 = agent.create_component("Test Plugin".into(), "com.test_plugin.plugin_name".into());
+/// agent.create_metric_template(&mut c1, "Component/Queue/{queue}/Depth[messages]".into(),
+///     MetricKind::Gauge).unwrap();
+/// agent.register_component(c1).unwrap();
+/// 
+/// agent.run(|agent| {
+///     for &(queue, depth) in &[("emails", 12), ("invoices", 3)]{
+///         agent.report_labeled(
+///             "com.test_plugin.plugin_name".into(),
+///             "Component/Queue/{queue}/Depth[messages]".into(),
+///             &[("queue", queue)],
+///             depth as f64
+///         ).unwrap();
+///     }
+/// });
+/// ```
+/// 
+/// # rollups
+/// A component can declare rollup rules that combine all of its metrics matching a pattern into one more metric at deliver time, so the plugin does not have to compute and report the total itself. A ```*``` segment matches any single segment. ```Rollup::Sum``` adds up the interval average of each matched metric, ```Rollup::Merge``` merges their summaries:
+/// 
+/// ```no_run
+/// # use newrelic_plugin::plugin::agent::Agent;
+/// use newrelic_plugin::plugin::rollup::Rollup;
+/// 
+/// # let agent: Agent<()> = Agent::new("<license_key>".into(), "1.0.0".into(), "host".into(), 1234);
+/// let mut c1 = agent.create_component("Test Plugin".into(), "com.test_plugin.plugin_name".into());
+/// agent.create_rollup(&mut c1,
+///     "Component/Request/Rate/*[requests/second]".into(),
+///     "Component/Request/Rate/all[requests/second]".into(),
+///     Rollup::Sum
+/// ).unwrap();
+/// ```
+/// 
+/// # derived metrics
+/// Ratios such as a cache hit rate can be declared as derived metrics instead of being computed in the cycle function. A derived metric is an expression over other metrics of the same component, referenced by name in braces, and is computed from their interval averages (and rollups) right before delivery. Expressions support numbers, ```+ - * /```, parentheses, ```min(...)``` and ```max(...)```. When a referenced metric has no value in the interval, or a division by zero occurs, the derived metric is left out of the payload:
+/// 
+/// ```no_run
+/// # use newrelic_plugin::plugin::agent::Agent;
+/// # let agent: Agent<()> = Agent::new("<license_key>".into(), "1.0.0".into(), "host".into(), 1234);
+/// let mut c1 = agent.create_component("Test Plugin".into(), "com.test_plugin.plugin_name".into());
+/// agent.create_derived_metric(&mut c1,
+///     "Component/Cache/HitRate[percent]".into(),
+///     "100 * {Component/Cache/Hits[hits]} / ({Component/Cache/Hits[hits]} + {Component/Cache/Misses[misses]})".into()
+/// ).unwrap();
+/// ```
+/// 
+/// # attributes
+/// Instead of encoding region, shard or environment into every metric name, attributes can be attached to a component and to single metrics. The Plugin API has no dimensions, so attributes are encoded into the delivered metric path as ```key=value``` segments after the ```Component``` prefix (sorted by key, metric attributes overriding component attributes). Snapshots keep the plain metric names and list the attributes separately, so exporters that support dimensions can use them as such:
+/// 
+/// ```no_run
+/// # use newrelic_plugin::plugin::agent::Agent;
+/// # let mut agent: Agent<()> = Agent::new("<license_key>".into(), "1.0.0".into(), "host".into(), 1234);
+/// let mut c1 = agent.create_component("Test Plugin".into(), "com.test_plugin.plugin_name".into());
+/// agent.set_component_attribute(&mut c1, "region".into(), "eu".into()).unwrap();
+/// let rate = agent.create_metric(&mut c1, "Component/Request/Rate[requests/second]".into()).unwrap();
+/// agent.register_component(c1).unwrap();
+/// // delivered as Component/region=eu/shard=3/Request/Rate[requests/second]
+/// agent.set_metric_attribute(rate, "shard".into(), "3".into()).unwrap();
+/// ```
+/// 
+/// # components at runtime
+/// Components can be registered, replaced and unregistered at any time, also from the cycle function while the agent is running. Components are identified by name and GUID together; registering the same pair twice fails with ```ComponentError::Duplicate```. Several components may share a plugin GUID, such as one per Redis instance. Reports by GUID alone then fail with ```ReportError::AmbiguousComponent```; the ```report_component_*``` functions take the component name as well. A removed component's values that were not delivered yet are dropped:
+/// 
+/// ```no_run
+/// # use newrelic_plugin::plugin::agent::Agent;
+/// # let agent: Agent<()> = Agent::new("<license_key>".into(), "1.0.0".into(), "host".into(), 1234);
+/// agent.run(|agent| {
+///     if !agent.is_registered("redis-6380", "com.test_plugin.redis"){
+///         let mut c2 = agent.create_component("redis-6380".into(), "com.test_plugin.redis".into());
+///         agent.create_metric(&mut c2, "Component/Memory/Used[bytes]".into()).unwrap();
+///         agent.register_component(c2).unwrap();
+///     }
+///     agent.report_component_metric("redis-6380", "com.test_plugin.redis",
+///         "Component/Memory/Used[bytes]".into(), 1024f64).unwrap();
+///     // when the instance is gone
+///     agent.unregister_component("redis-6379", "com.test_plugin.redis").ok();
+/// });
+/// ```
+/// 
+/// # component discovery
+/// For services whose instances come and go, such as all Redis instances on a host, the agent can discover components itself. A ```ComponentDiscovery``` returns the components (name, GUID and metric templates) that should be monitored; the agent polls it every ```discovery_cycle``` seconds, registers new components and unregisters the ones it discovered before that are gone. Components registered by hand are left alone. ```GlobDiscovery``` creates a component for each file matching a pattern, ```StaticDiscovery``` takes a fixed list or reads it from a YAML file:
+/// 
+/// ```no_run
+/// # use newrelic_plugin::plugin::agent::Agent;
+/// use newrelic_plugin::plugin::discovery::{GlobDiscovery, StaticDiscovery, TemplateSpec};
+/// use newrelic_plugin::plugin::metric_kind::MetricKind;
+/// 
+/// # let mut agent: Agent<()> = Agent::new("<license_key>".into(), "1.0.0".into(), "host".into(), 1234);
+/// agent.add_discovery(GlobDiscovery::new("/var/run/redis/*.sock", "com.test_plugin.redis".into(), vec![
+///     TemplateSpec{ template: "Component/Memory/{kind}[bytes]".into(), kind: MetricKind::Gauge }
+/// ]));
+/// agent.add_discovery(StaticDiscovery::from_file("components.yml"));
+/// agent.run(|agent| {
+///     // discovered components share the GUID and are told apart by name, e.g. "6379" for /var/run/redis/6379.sock
+///     agent.report_component_labeled("6379", "com.test_plugin.redis",
+///         "Component/Memory/{kind}[bytes]".into(), &[("kind", "used")], 1024f64).ok();
+/// });
+/// ```
+/// 
+/// # errors
+/// All ```report_*``` and ```record*``` functions return a ```Result```. Reporting to a GUID that was never registered fails with ```ReportError::UnknownComponent```, to a GUID shared by several components with ```ReportError::AmbiguousComponent```, and reporting to a metric name that was never created fails with ```ReportError::UnknownMetric```. Plugins that prefer to create metrics on first use can opt in with ```set_auto_create_metrics```:
+/// 
+/// ```no_run
+/// # use newrelic_plugin::plugin::agent::Agent;
+/// # let mut agent: Agent<()> = Agent::new("<license_key>".into(), "1.0.0".into(), "host".into(), 1234);
+/// agent.set_auto_create_metrics(true);
+/// ```
+/// 
+/// Values that are NaN or infinite fail with ```ReportError::InvalidValue``` (or are clamped, see ```invalid_values``` below), and summaries containing them fail with ```SummaryError::NonFinite```. Each rejected sample is counted on its metric and can be read with ```agent.invalid_samples(handle)``` to track down where a plugin produces them. Summaries that overflow while aggregating are left out of the payload and logged as errors.
+/// 
+/// # summaries
+/// When a plugin already aggregates several samples itself, it can report them at once as a ```MetricSummary```. Summaries are validated (```count > 0```, ```min <= max```) before being aggregated:
+/// 
+/// ```no_run
+/// # use newrelic_plugin::plugin::agent::Agent;
+/// use newrelic_plugin::plugin::summary::MetricSummary;
+/// 
+/// fn cycle(agent: &mut Agent<()>){
+///     let summary = MetricSummary::from_samples(&[120.0, 95.0, 143.0]).unwrap();
+///     agent.report_summary(
+///         "com.test_plugin.plugin_name".into(),
+///         "Component/Request/Latency/host1[ms]".into(),
+///         summary
+///     ).unwrap();
+/// }
+/// ```
+/// 
+/// ```agent.statistics(handle)``` returns the mean, variance and standard deviation of the values recorded to a metric in the current interval. They are accumulated with Welford's algorithm, so they stay precise for large values where computing the variance from ```sum_of_squares``` would not. The payload sent to NewRelic is unchanged.
+/// 
+/// # units
+/// The unit declared in brackets at the end of a metric name is parsed into a ```Unit```. Values measured in another unit of the same kind can be reported with ```report_quantity```, which converts them to the metric's unit before aggregation. ```Duration```s convert to any time unit:
+/// 
+/// ```no_run
+/// # use newrelic_plugin::plugin::agent::Agent;
+/// use newrelic_plugin::plugin::unit::{Quantity, Unit};
+/// use std::time::Duration;
+/// 
+/// fn cycle(agent: &mut Agent<()>){
+///     agent.report_quantity(
+///         "com.test_plugin.plugin_name".into(),
+///         "Component/Request/Latency/host1[ms]".into(),
+///         Duration::from_micros(1500)
+///     ).unwrap();
+///     agent.report_quantity(
+///         "com.test_plugin.plugin_name".into(),
+///         "Component/File/Size/host1[bytes]".into(),
+///         Quantity::new(4.0, Unit::Kilobytes)
+///     ).unwrap();
+/// }
+/// ```
+/// 
+/// # timing
+/// Durations can be measured with ```time```, which returns a guard that reports the elapsed time to the metric, in the metric's unit, when it is dropped. ```time_fn``` times a closure:
+/// 
+/// ```no_run
+/// # use newrelic_plugin::plugin::agent::Agent;
+/// fn cycle(agent: &mut Agent<()>){
+///     let poll_duration = agent.metric_handle(
+///         "com.test_plugin.plugin_name", "Component/Poll/Duration[ms]").unwrap();
+///     let depth = agent.time_fn(poll_duration, |_agent| 42);
+///     agent.report_metric(
+///         "com.test_plugin.plugin_name".into(),
+///         "Component/Queue/Depth/jobs[messages]".into(),
+///         depth as f64
+///     ).unwrap();
+/// }
+/// ```
+/// 
+/// # recording from other threads
+/// ```run``` takes the agent by value, so reporting normally happens inside the cycle function. Services that embed the agent can get a ```Recorder``` with ```agent.recorder()```: it is cheap to clone, ```Send + Sync```, and records by metric handle from any thread. Recorded values are aggregated per metric in sharded locks, so memory does not grow with the number of values, and applied by the agent loop at the end of each poll cycle, before delivery; errors are logged at that point. Counters keep only their latest reading and when it was taken:
+/// 
+/// ```no_run
+/// # use newrelic_plugin::plugin::agent::Agent;
+/// use std::thread;
+/// 
+/// # let mut agent: Agent<()> = Agent::new("<license_key>".into(), "1.0.0".into(), "host".into(), 1234);
+/// let mut c1 = agent.create_component("Test Plugin".into(), "com.test_plugin.plugin_name".into());
+/// let latency = agent.create_metric(&mut c1, "Component/Request/Latency[ms]".into()).unwrap();
+/// agent.register_component(c1).unwrap();
+/// 
+/// let recorder = agent.recorder();
+/// thread::spawn(move || agent.run(|_| {}));
+/// // in any request handler thread
+/// recorder.record(latency, 12.5);
+/// ```
+/// 
+/// # snapshots
+/// ```agent.snapshot()``` returns a ```Snapshot``` of every component with the metric summaries of the current interval, exactly as they would be delivered now (including percentiles, smoothing, rollup and derived metrics). ```agent.last_delivered_snapshot()``` returns the last one that was delivered successfully. Snapshots are plain data and serialise with serde, so plugins can log, assert on or expose them:
+/// 
+/// ```no_run
+/// # use newrelic_plugin::plugin::agent::Agent;
+/// fn cycle(agent: &mut Agent<()>){
+///     let snapshot = agent.snapshot();
+///     let latency = snapshot.component("com.test_plugin.plugin_name")
+///         .and_then(|component| component.metric("Component/Request/Latency[ms]"));
+///     if let Some(latency) = latency{
+///         println!("{} requests so far, {} ms in total", latency.count, latency.total);
+///     }
+/// }
+/// ```
+/// 
 /// # config
 /// 
 /// NewRelic plugin reads configuration from a ```config.yml``` file located in the current working directory. If no ```config.yml``` file is present, default values are used. Possible config keys and values:
@@ -81,6 +429,12 @@ use std::fmt;
 /// | log4rs_file | log4rs config file | log4rs.yml |
 /// | deliver_cycle | metric reporting frequency | 60 (seconds) |
 /// | poll_cycle | poll cycle frequency | 20 (seconds) |
+/// | component_metric_limit | maximum number of metrics a component creates while reporting. ```template_metric_limit``` is accepted as a deprecated alias | 100 |
+/// | agent_metric_limit | maximum number of metrics all components create while reporting | 1000 |
+/// | empty_metrics | what to send for metrics without values in a deliver interval: omit, zeros or repeat_last. Gauges repeat their last value unless overridden with ```set_empty_policy``` | omit |
+/// | invalid_values | what to do with NaN or infinite reported values: reject (return ```ReportError::InvalidValue```) or clamp infinities to ±1e144, small enough that their sums stay finite. Invalid samples are counted per metric, see ```invalid_samples``` | reject |
+/// | discovery_cycle | component discovery cycle in seconds, see ```add_discovery``` | 60 |
+/// | metric_ttl | number of deliver cycles without reports after which a metric is no longer sent; components without such metrics are left out of the payload. Expired metrics created from templates or in auto-create mode are removed and no longer count towards the metric limits. 0 disables expiry | 0 |
 /// 
 /// # logging
 /// 
@@ -113,14 +467,21 @@ use std::fmt;
 ///     appenders:
 ///       - plugin
 /// ```
-
-
 pub struct Agent<T>{
     context: Context,
     config: Config,
+    recorder: Recorder,
+    discoveries: Vec<Discovery>,
+    last_discovery: Option<i64>,
     state: Option<T>
 }
 
+/// A discovery provider and the components it registered, by name and GUID.
+struct Discovery{
+    provider: Box<dyn ComponentDiscovery>,
+    discovered: HashSet<(String, String)>
+}
+
 
 impl<T> fmt::Display for Agent<T> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
@@ -134,9 +495,23 @@ impl<T> Agent<T>{
     pub fn new(license_key: String, version: String, host: String, pid: u64) -> Self{
         let config = Config::new();
         let _ = init_file(config.log4rs_file(), Default::default());
-        return Agent{
-            context: Context::new(license_key, version, host, pid),
-            config: config,
+        Agent::with_config(license_key, version, host, pid, config)
+    }
+
+    /// Creates an agent without setting up logging.
+    pub(crate) fn with_config(license_key: String, version: String, host: String, pid: u64,
+        config: Config) -> Self{
+        let mut context = Context::new(license_key, version, host, pid);
+        context.agent_metric_limit = config.agent_metric_limit();
+        context.metric_ttl = config.metric_ttl();
+        context.empty_policy = config.empty_metrics();
+        context.invalid_value_policy = config.invalid_values();
+        Agent{
+            context,
+            recorder: Recorder::new(config.invalid_values()),
+            config,
+            discoveries: vec![],
+            last_discovery: None,
             state: None
         }
     }
@@ -150,32 +525,296 @@ impl<T> Agent<T>{
     }
 
     pub fn create_component(&self, name: String, guid: String) -> Component{
-        Component::new(name, guid)
+        let mut component = Component::new(name, guid);
+        component.set_metric_limit(self.config.component_metric_limit());
+        component
+    }
+
+    pub fn create_metric(&self, component: &mut Component, name: String) -> Result<MetricHandle, MetricNameError>{
+        self.create_metric_with_kind(component, name, MetricKind::Timeslice)
+    }
+
+    pub fn create_metric_with_kind(&self, component: &mut Component, name: String,
+        kind: MetricKind) -> Result<MetricHandle, MetricNameError>{
+        match MetricName::parse(&name){
+            Ok(metric_name) => Ok(component.add_metric_with_kind(&metric_name, kind)),
+            Err(e) => {
+                error!(target: "agent", "Invalid metric name {:?}. Error: {}", name, e);
+                Err(e)
+            }
+        }
+    }
+
+    pub fn set_component_attribute(&self, component: &mut Component, key: String,
+        value: String) -> Result<(), MetricNameError>{
+        match validate_attribute(&key, &value){
+            Ok(()) => {
+                component.attributes.insert(key, value);
+                Ok(())
+            },
+            Err(e) => {
+                error!(target: "agent", "Invalid attribute {:?}={:?}. Error: {}", key, value, e);
+                Err(e)
+            }
+        }
+    }
+
+    pub fn create_rollup(&self, component: &mut Component, pattern: String, target: String,
+        rollup: Rollup) -> Result<(), MetricNameError>{
+        match RollupRule::parse(&pattern, &target, rollup){
+            Ok(rule) => {
+                component.add_rollup(rule);
+                Ok(())
+            },
+            Err(e) => {
+                error!(target: "agent", "Invalid rollup {:?} -> {:?}. Error: {}", pattern, target, e);
+                Err(e)
+            }
+        }
+    }
+
+    pub fn create_derived_metric(&self, component: &mut Component, name: String,
+        expression: String) -> Result<(), ExpressionError>{
+        let derived = MetricName::parse(&name).map_err(ExpressionError::from)
+            .and_then(|metric_name| Ok((metric_name, Expression::parse(&expression)?)));
+        match derived{
+            Ok((metric_name, expression)) => {
+                component.add_derived_metric(metric_name, expression);
+                Ok(())
+            },
+            Err(e) => {
+                error!(target: "agent", "Invalid derived metric {:?} = {:?}. Error: {}", name, expression, e);
+                Err(e)
+            }
+        }
+    }
+
+    pub fn create_metric_template(&self, component: &mut Component, template: String,
+        kind: MetricKind) -> Result<(), TemplateError>{
+        match MetricTemplate::parse(&template){
+            Ok(metric_template) => {
+                component.add_template(metric_template, kind);
+                Ok(())
+            },
+            Err(e) => {
+                error!(target: "agent", "Invalid metric template {:?}. Error: {}", template, e);
+                Err(e)
+            }
+        }
+    }
+
+    /// Overrides the agent's `empty_metrics` setting for one metric of a registered component.
+    pub fn set_empty_policy(&mut self, handle: MetricHandle,
+        policy: EmptyPolicy) -> Result<(), ReportError>{
+        self.context.set_empty_policy(handle, policy)
+    }
+
+    /// Adds an attribute to the metric, overriding a component attribute with the same key.
+    pub fn set_metric_attribute(&mut self, handle: MetricHandle, key: String,
+        value: String) -> Result<(), ReportError>{
+        self.context.set_metric_attribute(handle, key, value)
+    }
+
+    /// Chooses when the metric's aggregated values are cleared. Metrics reset on every delivery by default.
+    pub fn set_reset_policy(&mut self, handle: MetricHandle,
+        policy: ResetPolicy) -> Result<(), ReportError>{
+        self.context.set_reset_policy(handle, policy)
+    }
+
+    /// Sends a smoothed companion of the metric, e.g. `Component/Connections/ewma[connections]`.
+    /// A metric has at most one `Ewma`; adding another alpha fails with `ConflictingSmoothing`.
+    pub fn add_smoothing(&mut self, handle: MetricHandle,
+        smoothing: Smoothing) -> Result<(), ReportError>{
+        self.context.add_smoothing(handle, smoothing)
+    }
+
+    /// Adds a component to the agent. Fails if a component with the same name and GUID is
+    /// already registered. Can also be called from the cycle function while the agent is running.
+    pub fn register_component(&mut self, component: Component) -> Result<(), ComponentError>{
+        self.context.register_component(component).map_err(|e| {
+            error!(target: "agent", "Could not register component. Error: {}", e);
+            e
+        })
+    }
+
+    /// Removes a component and returns it. Values it aggregated since the last delivery are not sent.
+    pub fn unregister_component(&mut self, name: &str, guid: &str) -> Result<Component, ComponentError>{
+        self.context.unregister_component(name, guid)
+    }
+
+    /// Swaps the registered component with the same name and GUID for `component` and returns
+    /// the old one. Metric handles of the old component become unknown.
+    pub fn replace_component(&mut self, component: Component) -> Result<Component, ComponentError>{
+        self.context.replace_component(component)
+    }
+
+    pub fn is_registered(&self, name: &str, guid: &str) -> bool{
+        self.context.is_registered(name, guid)
     }
 
-    pub fn create_metric(&self, component: &mut Component, name: String){
-        component.add_metric(name);
+    /// When enabled, reporting to a metric name the component does not have yet creates it
+    /// as a timeslice metric instead of failing with `ReportError::UnknownMetric`.
+    pub fn set_auto_create_metrics(&mut self, enabled: bool){
+        self.context.auto_create_metrics = enabled;
     }
 
-    pub fn register_component(&mut self, component: Component){
-        self.context.register_component(component);
+    pub fn report_metric(&mut self, component_guid: String, metric_name: String,
+        value: f64) -> Result<f64, ReportError>{
+        self.context.report_metric(component_guid, metric_name, value)
     }
 
-    pub fn report_metric(&mut self, component_guid: String, metric_name: String, value: f64,
-        options: Option<(u64, f64, f64, f64)>) -> f64{
-        self.context.report_metric(component_guid, metric_name, value, options)
+    pub fn report_summary(&mut self, component_guid: String, metric_name: String,
+        summary: MetricSummary) -> Result<f64, ReportError>{
+        self.context.report_summary(component_guid, metric_name, summary)
+    }
+
+    pub fn report_quantity<Q: Into<Quantity>>(&mut self, component_guid: String, metric_name: String,
+        quantity: Q) -> Result<f64, ReportError>{
+        self.context.report_quantity(component_guid, metric_name, quantity.into())
+    }
+
+    pub fn report_labeled(&mut self, component_guid: String, template: String,
+        labels: &[(&str, &str)], value: f64) -> Result<f64, ReportError>{
+        self.context.report_labeled(component_guid, template, labels, value)
+    }
+
+    /// Looks up a metric of the only component registered with the GUID. Returns `None` if no
+    /// component or several components have it.
+    pub fn metric_handle(&self, component_guid: &str, metric_name: &str) -> Option<MetricHandle>{
+        self.context.metric_handle(component_guid, metric_name)
+    }
+
+    /// Looks up a metric of the component registered with the name and GUID.
+    pub fn component_metric_handle(&self, component_name: &str, component_guid: &str,
+        metric_name: &str) -> Option<MetricHandle>{
+        self.context.component_metric_handle(component_name, component_guid, metric_name)
+    }
+
+    /// Like `report_metric`, for components that share their GUID with others.
+    pub fn report_component_metric(&mut self, component_name: &str, component_guid: &str,
+        metric_name: String, value: f64) -> Result<f64, ReportError>{
+        self.context.report_component_metric(component_name, component_guid, metric_name, value)
+    }
+
+    /// Like `report_summary`, for components that share their GUID with others.
+    pub fn report_component_summary(&mut self, component_name: &str, component_guid: &str,
+        metric_name: String, summary: MetricSummary) -> Result<f64, ReportError>{
+        self.context.report_component_summary(component_name, component_guid, metric_name, summary)
+    }
+
+    /// Like `report_quantity`, for components that share their GUID with others.
+    pub fn report_component_quantity<Q: Into<Quantity>>(&mut self, component_name: &str, component_guid: &str,
+        metric_name: String, quantity: Q) -> Result<f64, ReportError>{
+        self.context.report_component_quantity(component_name, component_guid, metric_name, quantity.into())
+    }
+
+    /// Like `report_labeled`, for components that share their GUID with others.
+    pub fn report_component_labeled(&mut self, component_name: &str, component_guid: &str,
+        template: String, labels: &[(&str, &str)], value: f64) -> Result<f64, ReportError>{
+        self.context.report_component_labeled(component_name, component_guid, template, labels, value)
+    }
+
+    pub fn record(&mut self, handle: MetricHandle, value: f64) -> Result<f64, ReportError>{
+        self.context.record(handle, value)
+    }
+
+    pub fn record_summary(&mut self, handle: MetricHandle,
+        summary: MetricSummary) -> Result<f64, ReportError>{
+        self.context.record_summary(handle, summary)
+    }
+
+    pub fn record_quantity<Q: Into<Quantity>>(&mut self, handle: MetricHandle,
+        quantity: Q) -> Result<f64, ReportError>{
+        self.context.record_quantity(handle, quantity.into())
+    }
+
+    /// Mean, variance and standard deviation of the values recorded to the metric in the current interval.
+    pub fn statistics(&self, handle: MetricHandle) -> Result<Statistics, ReportError>{
+        self.context.statistics(handle)
+    }
+
+    /// Number of NaN or infinite samples reported to the metric since it was created.
+    pub fn invalid_samples(&self, handle: MetricHandle) -> Result<u64, ReportError>{
+        self.context.invalid_samples(handle)
+    }
+
+    pub fn time(&mut self, handle: MetricHandle) -> Timer<'_, T>{
+        Timer::new(self, handle)
+    }
+
+    pub fn time_fn<F, R>(&mut self, handle: MetricHandle, f: F) -> R
+        where F: FnOnce(&mut Agent<T>) -> R{
+        let mut timer = self.time(handle);
+        f(&mut timer)
+    }
+
+    /// Lets `discovery` register and retire components while the agent runs. Discoveries are
+    /// polled before the first cycle and then every `discovery_cycle` seconds.
+    pub fn add_discovery<D: ComponentDiscovery + 'static>(&mut self, discovery: D){
+        self.discoveries.push(Discovery{
+            provider: Box::new(discovery),
+            discovered: HashSet::new()
+        });
+    }
+
+    fn discovery_due(&self) -> bool{
+        match self.last_discovery{
+            Some(last_discovery) => Utc::now().timestamp() - last_discovery >= self.config.discovery_cycle(),
+            None => true
+        }
+    }
+
+    fn discover_components(&mut self){
+        let mut discoveries = mem::take(&mut self.discoveries);
+        for discovery in &mut discoveries{
+            let specs = match discovery.provider.discover(){
+                Ok(specs) => specs,
+                Err(e) => {
+                    error!(target: "agent", "Component discovery failed, keeping current components. Error: {}", e);
+                    continue;
+                }
+            };
+            self.context.sync_discovered(&mut discovery.discovered, specs, self.config.component_metric_limit());
+        }
+        self.discoveries = discoveries;
+        self.last_discovery = Some(Utc::now().timestamp());
     }
 
     fn context_duration(&self) -> i64{
-        if self.context.last_reported.is_some(){
-            let now: DateTime<Utc> = Utc::now();
-            return now.timestamp() - self.context.last_reported.unwrap();
-        }else{
-            MAX
+        match self.context.last_reported{
+            Some(last_reported) => {
+                let now: DateTime<Utc> = Utc::now();
+                now.timestamp() - last_reported
+            },
+            None => i64::MAX
+        }
+    }
+
+    /// Metric summaries of the current interval, as they would be delivered now.
+    pub fn snapshot(&self) -> Snapshot{
+        self.context.snapshot()
+    }
+
+    /// Snapshot of the last interval delivered successfully, if any.
+    pub fn last_delivered_snapshot(&self) -> Option<&Snapshot>{
+        self.context.last_delivered()
+    }
+
+    /// Handle for recording metrics from other threads. All clones share the agent's buffers.
+    pub fn recorder(&self) -> Recorder{
+        self.recorder.clone()
+    }
+
+    fn apply_recorded(&mut self){
+        for (handle, pending) in self.recorder.drain(){
+            if let Err(e) = self.context.apply_recorded(handle, pending){
+                error!(target: "agent", "Could not apply values recorded from another thread to metric {:?}. Error: {}", handle, e);
+            }
         }
     }
 
     fn finish_cycle(&mut self){
+        self.apply_recorded();
         info!(target: "agent", "Finishing cycle. Elapsed: {}.", self.context_duration());
         if self.context_duration() >= self.config.deliver_cycle(){
             info!(target: "agent", "Sending metrics.");
@@ -186,6 +825,9 @@ impl<T> Agent<T>{
 
     pub fn run<F>(mut self, mut cycle_fn: F) where F: FnMut(&mut Agent<T>){
         loop{
+            if !self.discoveries.is_empty() && self.discovery_due(){
+                self.discover_components();
+            }
             info!(target: "agent", "Starting cycle fn.");
             cycle_fn(&mut self);
             self.finish_cycle();
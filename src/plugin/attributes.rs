@@ -0,0 +1,51 @@
+use plugin::metric_name::{MetricName, MetricNameError};
+use std::collections::BTreeMap;
+
+/// Separates key and value of an attribute encoded as a metric name segment.
+const SEPARATOR: char = '=';
+
+///
+/// Key/value attributes of a component or metric, such as region, shard or environment, sorted by key.
+///
+/// The Plugin API has no dimensions, so attributes are encoded into the metric path as
+/// `key=value` segments right after the `Component` prefix. Snapshots keep them as attributes,
+/// so exporters that support dimensions can use them as such.
+///
+/// # Examples
+/// ```
+/// use newrelic_plugin::plugin::attributes::{Attributes, encode_name};
+///
+/// let mut attributes = Attributes::new();
+/// attributes.insert("shard".into(), "3".into());
+/// attributes.insert("region".into(), "eu".into());
+/// assert_eq!(encode_name("Component/Request/Rate[requests/second]", &attributes).unwrap(),
+///     "Component/region=eu/shard=3/Request/Rate[requests/second]");
+/// ```
+pub type Attributes = BTreeMap<String, String>;
+
+/// Checks that an attribute can be encoded as a metric name segment.
+pub fn validate_attribute(key: &str, value: &str) -> Result<(), MetricNameError>{
+    if key.contains(SEPARATOR){
+        return Err(MetricNameError::InvalidCharacter(SEPARATOR));
+    }
+    if key.is_empty() || value.is_empty(){
+        return Err(MetricNameError::EmptySegment);
+    }
+    MetricName::builder().segment(key).segment(value).unit("attribute").build().map(|_| ())
+}
+
+/// Metric name with `attributes` encoded into its path.
+pub fn encode_name(metric_name: &str, attributes: &Attributes) -> Result<String, MetricNameError>{
+    if attributes.is_empty(){
+        return Ok(metric_name.to_string());
+    }
+    let name = MetricName::parse(metric_name)?;
+    let mut builder = MetricName::builder();
+    for (key, value) in attributes{
+        builder = builder.segment(format!("{}{}{}", key, SEPARATOR, value));
+    }
+    for segment in name.segments(){
+        builder = builder.segment(segment.as_str());
+    }
+    Ok(builder.unit(name.unit()).build()?.to_string())
+}
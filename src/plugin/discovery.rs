@@ -0,0 +1,177 @@
+use plugin::metric_kind::MetricKind;
+use serde_yaml::from_str as from_yaml;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+///
+/// Component the agent should monitor, as returned by a `ComponentDiscovery`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ComponentSpec{
+    pub name: String,
+    pub guid: String,
+    #[serde(default)]
+    pub templates: Vec<TemplateSpec>
+}
+
+///
+/// Metric template created on a discovered component, e.g. `Component/Memory/{kind}[bytes]`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TemplateSpec{
+    pub template: String,
+    #[serde(default)]
+    pub kind: MetricKind
+}
+
+///
+/// Source of the set of components the agent should monitor, for services whose instances come
+/// and go, e.g. all Redis instances on a host. The agent polls every registered discovery each
+/// `discovery_cycle` seconds, registers components that appeared and unregisters the components
+/// it discovered earlier that are gone. Components registered by hand are never removed, and
+/// a discovered component unregistered by hand is registered again while it is still discovered.
+///
+/// Components are matched by name and GUID; changing the templates of a component that is
+/// already registered has no effect until it disappears and is discovered again.
+///
+/// If discovery fails the agent logs the error and keeps the current components.
+pub trait ComponentDiscovery: Send{
+    fn discover(&mut self) -> io::Result<Vec<ComponentSpec>>;
+}
+
+///
+/// Discovers one component per file matching a glob pattern, named after the file without its
+/// extension. Wildcards (`*` for any characters, `?` for a single character) are supported in
+/// the file name only, e.g. `/etc/redis/*.conf`.
+///
+/// # Examples
+/// ```no_run
+/// # use newrelic_plugin::plugin::agent::Agent;
+/// use newrelic_plugin::plugin::discovery::{GlobDiscovery, TemplateSpec};
+/// use newrelic_plugin::plugin::metric_kind::MetricKind;
+///
+/// # let mut agent: Agent<()> = Agent::new("<license_key>".into(), "1.0.0".into(), "host".into(), 1234);
+/// agent.add_discovery(GlobDiscovery::new("/etc/redis/*.conf", "com.test_plugin.redis".into(), vec![
+///     TemplateSpec{ template: "Component/Memory/{kind}[bytes]".into(), kind: MetricKind::Gauge }
+/// ]));
+/// ```
+#[derive(Debug, Clone)]
+pub struct GlobDiscovery{
+    pattern: String,
+    guid: String,
+    templates: Vec<TemplateSpec>
+}
+
+impl GlobDiscovery{
+    pub fn new(pattern: &str, guid: String, templates: Vec<TemplateSpec>) -> Self{
+        GlobDiscovery{
+            pattern: pattern.to_string(),
+            guid,
+            templates
+        }
+    }
+}
+
+impl ComponentDiscovery for GlobDiscovery {
+    fn discover(&mut self) -> io::Result<Vec<ComponentSpec>>{
+        let path = Path::new(&self.pattern);
+        let directory = match path.parent(){
+            Some(directory) if !directory.as_os_str().is_empty() => directory,
+            _ => Path::new(".")
+        };
+        let file_pattern: Vec<char> = match path.file_name().and_then(|name| name.to_str()){
+            Some(file_pattern) => file_pattern.chars().collect(),
+            None => return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                format!("Glob pattern {:?} has no file name", self.pattern)))
+        };
+        let mut specs = vec![];
+        for entry in fs::read_dir(directory)?{
+            let path = entry?.path();
+            let matched = path.file_name().and_then(|name| name.to_str())
+                .is_some_and(|name| glob_match(&file_pattern, &name.chars().collect::<Vec<char>>()));
+            if !matched{
+                continue;
+            }
+            if let Some(name) = path.file_stem().and_then(|stem| stem.to_str()){
+                specs.push(ComponentSpec{
+                    name: name.to_string(),
+                    guid: self.guid.clone(),
+                    templates: self.templates.clone()
+                });
+            }
+        }
+        specs.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(specs)
+    }
+}
+
+fn glob_match(pattern: &[char], name: &[char]) -> bool{
+    match pattern.split_first(){
+        None => name.is_empty(),
+        Some((&'*', rest)) => (0..=name.len()).any(|skip| glob_match(rest, &name[skip..])),
+        Some((&'?', rest)) => !name.is_empty() && glob_match(rest, &name[1..]),
+        Some((c, rest)) => name.first() == Some(c) && glob_match(rest, &name[1..])
+    }
+}
+
+///
+/// Discovers a fixed list of components, e.g. read from a YAML file. A file is re-read on every
+/// discovery, so components can be added or removed by editing it.
+///
+/// **Example components file:**
+///
+/// ```yml
+/// - name: redis-6379
+///   guid: com.test_plugin.redis
+///   templates:
+///     - template: "Component/Memory/{kind}[bytes]"
+///       kind: gauge
+/// ```
+#[derive(Debug, Clone)]
+pub struct StaticDiscovery{
+    specs: Vec<ComponentSpec>,
+    path: Option<String>
+}
+
+impl StaticDiscovery{
+    pub fn new(specs: Vec<ComponentSpec>) -> Self{
+        StaticDiscovery{ specs, path: None }
+    }
+
+    pub fn from_file(path: &str) -> Self{
+        StaticDiscovery{ specs: vec![], path: Some(path.to_string()) }
+    }
+}
+
+impl ComponentDiscovery for StaticDiscovery {
+    fn discover(&mut self) -> io::Result<Vec<ComponentSpec>>{
+        if let Some(ref path) = self.path{
+            self.specs = from_yaml(&fs::read_to_string(path)?)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+        }
+        Ok(self.specs.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ComponentDiscovery, ComponentSpec, GlobDiscovery};
+    use std::env;
+    use std::fs;
+
+    #[test]
+    fn glob_discovery_names_components_after_their_files(){
+        let directory = env::temp_dir().join(format!("newrelic_plugin_glob_{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        for file in &["6379.sock", "6380.sock", "6380.pid"]{
+            fs::write(directory.join(file), "").unwrap();
+        }
+        let pattern = directory.join("*.sock");
+        let mut discovery = GlobDiscovery::new(pattern.to_str().unwrap(), "com.example.redis".to_string(), vec![]);
+        let specs = discovery.discover();
+        fs::remove_dir_all(&directory).unwrap();
+        assert_eq!(specs.unwrap(), vec![
+            ComponentSpec{ name: "6379".to_string(), guid: "com.example.redis".to_string(), templates: vec![] },
+            ComponentSpec{ name: "6380".to_string(), guid: "com.example.redis".to_string(), templates: vec![] }
+        ]);
+    }
+}
@@ -0,0 +1,18 @@
+///
+/// What to deliver for a metric that received no values in a deliver interval.
+///
+/// * `Omit` leaves the metric out of the payload, so charts show a gap instead of a misleading zero.
+/// * `Zeros` sends an empty summary (count, total, min and max of 0).
+/// * `RepeatLast` sends the last reported value again, as a single sample. Nothing is sent
+///   until the metric has been reported once.
+///
+/// Gauges repeat their last value by default; every other metric kind uses the agent's
+/// `empty_metrics` setting, which defaults to `Omit`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EmptyPolicy{
+    #[default]
+    Omit,
+    Zeros,
+    RepeatLast
+}
@@ -0,0 +1,112 @@
+use plugin::metric_handle::MetricHandle;
+use plugin::metric_name::MetricNameError;
+use plugin::metric_template::TemplateError;
+use plugin::smoothing::Smoothing;
+use plugin::summary::SummaryError;
+use plugin::unit::UnitError;
+use std::error::Error;
+use std::fmt;
+
+///
+/// Reasons a reported value could not be recorded.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ReportError{
+    UnknownComponent(String),
+    UnknownNamedComponent{ name: String, guid: String },
+    AmbiguousComponent(String),
+    UnknownMetric{ component: String, metric: String },
+    UnknownHandle(MetricHandle),
+    UnknownTemplate{ component: String, template: String },
+    InvalidLabels(TemplateError),
+    CardinalityLimit{ component: String, metric: String },
+    InvalidValue{ metric: String, value: f64 },
+    InvalidSmoothing(Smoothing),
+    ConflictingSmoothing{ metric: String, smoothing: Smoothing },
+    SummaryOnCounter(String),
+    SummaryOnHistogram(String),
+    InvalidMetricName(MetricNameError),
+    InvalidSummary(SummaryError),
+    IncompatibleUnit(UnitError)
+}
+
+impl fmt::Display for ReportError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self{
+            ReportError::UnknownComponent(ref guid) => write!(f, "No component registered with GUID {}", guid),
+            ReportError::UnknownNamedComponent{ ref name, ref guid } =>
+                write!(f, "No component {} registered with GUID {}", name, guid),
+            ReportError::AmbiguousComponent(ref guid) =>
+                write!(f, "Several components share GUID {}, report to them by name and GUID or by metric handle", guid),
+            ReportError::UnknownMetric{ ref component, ref metric } =>
+                write!(f, "Component {} has no metric {}", component, metric),
+            ReportError::UnknownHandle(ref handle) =>
+                write!(f, "Metric handle {:?} does not belong to a registered component", handle),
+            ReportError::UnknownTemplate{ ref component, ref template } =>
+                write!(f, "Component {} has no metric template {}", component, template),
+            ReportError::InvalidLabels(ref e) => write!(f, "{}", e),
+            ReportError::CardinalityLimit{ ref component, ref metric } =>
+                write!(f, "Component {} reached its metric limit, counter {} cannot be folded and was dropped", component, metric),
+            ReportError::InvalidValue{ ref metric, value } =>
+                write!(f, "Invalid value {} reported to metric {}", value, metric),
+            ReportError::SummaryOnCounter(ref metric) =>
+                write!(f, "Counter {} takes readings, summaries cannot be reported to it", metric),
+            ReportError::SummaryOnHistogram(ref metric) =>
+                write!(f, "Histogram {} needs individual values for its percentiles, summaries cannot be reported to it", metric),
+            ReportError::InvalidSmoothing(ref smoothing) =>
+                write!(f, "Invalid smoothing {:?}, alpha must be in (0, 1] and window greater than zero", smoothing),
+            ReportError::ConflictingSmoothing{ ref metric, ref smoothing } =>
+                write!(f, "Metric {} already has a different smoothing sent as {}", metric, smoothing),
+            ReportError::InvalidMetricName(ref e) => write!(f, "{}", e),
+            ReportError::InvalidSummary(ref e) => write!(f, "{}", e),
+            ReportError::IncompatibleUnit(ref e) => write!(f, "{}", e)
+        }
+    }
+}
+
+impl Error for ReportError {}
+
+impl From<MetricNameError> for ReportError {
+    fn from(e: MetricNameError) -> Self{
+        ReportError::InvalidMetricName(e)
+    }
+}
+
+impl From<TemplateError> for ReportError {
+    fn from(e: TemplateError) -> Self{
+        ReportError::InvalidLabels(e)
+    }
+}
+
+impl From<SummaryError> for ReportError {
+    fn from(e: SummaryError) -> Self{
+        ReportError::InvalidSummary(e)
+    }
+}
+
+impl From<UnitError> for ReportError {
+    fn from(e: UnitError) -> Self{
+        ReportError::IncompatibleUnit(e)
+    }
+}
+
+///
+/// Reasons a component could not be registered, replaced or unregistered. Components are
+/// identified by name and GUID together, as several components may share a plugin GUID.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ComponentError{
+    Duplicate{ name: String, guid: String },
+    NotRegistered{ name: String, guid: String }
+}
+
+impl fmt::Display for ComponentError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self{
+            ComponentError::Duplicate{ ref name, ref guid } =>
+                write!(f, "Component {} with GUID {} is already registered", name, guid),
+            ComponentError::NotRegistered{ ref name, ref guid } =>
+                write!(f, "No component {} registered with GUID {}", name, guid)
+        }
+    }
+}
+
+impl Error for ComponentError {}
@@ -0,0 +1,336 @@
+use plugin::metric_name::{MetricName, MetricNameError};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+///
+/// Arithmetic expression over the metrics of a component, used for derived metrics.
+///
+/// Metrics are referenced by name in braces and stand for their average in the current
+/// interval. Expressions support numbers, `+`, `-`, `*`, `/`, parentheses and the `min(...)`
+/// and `max(...)` functions. Evaluation yields no value when a referenced metric has no value
+/// or when dividing by zero, so a derived metric is never sent as NaN or infinity.
+///
+/// # Examples
+/// ```
+/// use newrelic_plugin::plugin::expression::Expression;
+/// use std::collections::HashMap;
+///
+/// let hit_rate = Expression::parse(
+///     "100 * {Component/Cache/Hits[hits]} / ({Component/Cache/Hits[hits]} + {Component/Cache/Misses[misses]})"
+/// ).unwrap();
+/// let mut values = HashMap::new();
+/// values.insert("Component/Cache/Hits[hits]".to_string(), 30.0);
+/// values.insert("Component/Cache/Misses[misses]".to_string(), 10.0);
+/// assert_eq!(hit_rate.evaluate(&values), Some(75.0));
+///
+/// values.insert("Component/Cache/Hits[hits]".to_string(), 0.0);
+/// values.insert("Component/Cache/Misses[misses]".to_string(), 0.0);
+/// assert_eq!(hit_rate.evaluate(&values), None);
+/// assert!(Expression::parse("max(1, {Component/Cache/Hits[hits]}").is_err());
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expression{
+    expression: String,
+    root: Node
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Node{
+    Number(f64),
+    Metric(String),
+    Negate(Box<Node>),
+    Binary(Operator, Box<Node>, Box<Node>),
+    Function(Function, Vec<Node>)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Operator{
+    Add,
+    Subtract,
+    Multiply,
+    Divide
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Function{
+    Min,
+    Max
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token{
+    Number(f64),
+    Metric(String),
+    Identifier(String),
+    Symbol(char)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExpressionError{
+    UnexpectedEnd,
+    UnexpectedCharacter(char),
+    UnexpectedToken(String),
+    InvalidNumber(String),
+    UnknownFunction(String),
+    UnclosedReference,
+    InvalidMetric(MetricNameError)
+}
+
+impl fmt::Display for ExpressionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self{
+            ExpressionError::UnexpectedEnd => write!(f, "Expression ends unexpectedly"),
+            ExpressionError::UnexpectedCharacter(c) => write!(f, "Expression contains unexpected character {:?}", c),
+            ExpressionError::UnexpectedToken(ref token) => write!(f, "Expression contains unexpected {}", token),
+            ExpressionError::InvalidNumber(ref number) => write!(f, "Expression contains invalid number {}", number),
+            ExpressionError::UnknownFunction(ref name) =>
+                write!(f, "Expression calls unknown function {}, expected min or max", name),
+            ExpressionError::UnclosedReference => write!(f, "Expression has an unclosed {{ metric reference"),
+            ExpressionError::InvalidMetric(ref e) => write!(f, "{}", e)
+        }
+    }
+}
+
+impl Error for ExpressionError {}
+
+impl From<MetricNameError> for ExpressionError {
+    fn from(e: MetricNameError) -> Self{
+        ExpressionError::InvalidMetric(e)
+    }
+}
+
+impl fmt::Display for Expression {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.expression)
+    }
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self{
+            Token::Number(number) => write!(f, "number {}", number),
+            Token::Metric(ref name) => write!(f, "metric {{{}}}", name),
+            Token::Identifier(ref name) => write!(f, "name {}", name),
+            Token::Symbol(c) => write!(f, "{:?}", c)
+        }
+    }
+}
+
+impl FromStr for Expression {
+    type Err = ExpressionError;
+
+    fn from_str(expression: &str) -> Result<Self, Self::Err>{
+        Expression::parse(expression)
+    }
+}
+
+impl Expression{
+    pub fn parse(expression: &str) -> Result<Self, ExpressionError>{
+        let tokens = tokenize(expression)?;
+        let mut parser = Parser{ tokens, position: 0 };
+        let root = parser.sum()?;
+        if let Some(token) = parser.next(){
+            return Err(ExpressionError::UnexpectedToken(token.to_string()));
+        }
+        Ok(Expression{ expression: expression.to_string(), root })
+    }
+
+    /// Names of the metrics the expression refers to.
+    pub fn metrics(&self) -> Vec<&str>{
+        let mut metrics = vec![];
+        self.root.collect_metrics(&mut metrics);
+        metrics
+    }
+
+    /// Value of the expression given the interval values of the component's metrics.
+    pub fn evaluate(&self, values: &HashMap<String, f64>) -> Option<f64>{
+        self.root.evaluate(values).filter(|value| value.is_finite())
+    }
+}
+
+impl Node{
+    fn collect_metrics<'a>(&'a self, metrics: &mut Vec<&'a str>){
+        match *self{
+            Node::Number(_) => {},
+            Node::Metric(ref name) => {
+                if !metrics.contains(&name.as_str()){
+                    metrics.push(name);
+                }
+            },
+            Node::Negate(ref node) => node.collect_metrics(metrics),
+            Node::Binary(_, ref left, ref right) => {
+                left.collect_metrics(metrics);
+                right.collect_metrics(metrics);
+            },
+            Node::Function(_, ref arguments) => {
+                for argument in arguments{
+                    argument.collect_metrics(metrics);
+                }
+            }
+        }
+    }
+
+    fn evaluate(&self, values: &HashMap<String, f64>) -> Option<f64>{
+        match *self{
+            Node::Number(number) => Some(number),
+            Node::Metric(ref name) => values.get(name).cloned(),
+            Node::Negate(ref node) => node.evaluate(values).map(|value| -value),
+            Node::Binary(operator, ref left, ref right) => {
+                let left = left.evaluate(values)?;
+                let right = right.evaluate(values)?;
+                match operator{
+                    Operator::Add => Some(left + right),
+                    Operator::Subtract => Some(left - right),
+                    Operator::Multiply => Some(left * right),
+                    Operator::Divide if right == 0f64 => None,
+                    Operator::Divide => Some(left / right)
+                }
+            },
+            Node::Function(function, ref arguments) => {
+                let mut result: Option<f64> = None;
+                for argument in arguments{
+                    let value = argument.evaluate(values)?;
+                    result = Some(match (result, function){
+                        (None, _) => value,
+                        (Some(result), Function::Min) => result.min(value),
+                        (Some(result), Function::Max) => result.max(value)
+                    });
+                }
+                result
+            }
+        }
+    }
+}
+
+fn tokenize(expression: &str) -> Result<Vec<Token>, ExpressionError>{
+    let mut tokens = vec![];
+    let mut chars = expression.chars().peekable();
+    while let Some(&c) = chars.peek(){
+        if c.is_whitespace(){
+            chars.next();
+        }else if c == '{'{
+            chars.next();
+            let mut name = String::new();
+            loop{
+                match chars.next(){
+                    Some('}') => break,
+                    Some(c) => name.push(c),
+                    None => return Err(ExpressionError::UnclosedReference)
+                }
+            }
+            tokens.push(Token::Metric(MetricName::parse(&name)?.to_string()));
+        }else if c.is_ascii_digit() || c == '.'{
+            let mut number = String::new();
+            while let Some(&c) = chars.peek(){
+                if !(c.is_ascii_digit() || c == '.'){
+                    break;
+                }
+                number.push(c);
+                chars.next();
+            }
+            match number.parse(){
+                Ok(value) => tokens.push(Token::Number(value)),
+                Err(_) => return Err(ExpressionError::InvalidNumber(number))
+            }
+        }else if c.is_alphabetic(){
+            let mut name = String::new();
+            while let Some(&c) = chars.peek(){
+                if !c.is_alphanumeric(){
+                    break;
+                }
+                name.push(c);
+                chars.next();
+            }
+            tokens.push(Token::Identifier(name));
+        }else if "+-*/(),".contains(c){
+            tokens.push(Token::Symbol(c));
+            chars.next();
+        }else{
+            return Err(ExpressionError::UnexpectedCharacter(c));
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser{
+    tokens: Vec<Token>,
+    position: usize
+}
+
+impl Parser{
+    fn peek(&self) -> Option<&Token>{
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<Token>{
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn expect(&mut self, symbol: char) -> Result<(), ExpressionError>{
+        match self.next(){
+            Some(Token::Symbol(c)) if c == symbol => Ok(()),
+            Some(token) => Err(ExpressionError::UnexpectedToken(token.to_string())),
+            None => Err(ExpressionError::UnexpectedEnd)
+        }
+    }
+
+    fn sum(&mut self) -> Result<Node, ExpressionError>{
+        let mut node = self.product()?;
+        loop{
+            let operator = match self.peek(){
+                Some(&Token::Symbol('+')) => Operator::Add,
+                Some(&Token::Symbol('-')) => Operator::Subtract,
+                _ => return Ok(node)
+            };
+            self.next();
+            node = Node::Binary(operator, Box::new(node), Box::new(self.product()?));
+        }
+    }
+
+    fn product(&mut self) -> Result<Node, ExpressionError>{
+        let mut node = self.factor()?;
+        loop{
+            let operator = match self.peek(){
+                Some(&Token::Symbol('*')) => Operator::Multiply,
+                Some(&Token::Symbol('/')) => Operator::Divide,
+                _ => return Ok(node)
+            };
+            self.next();
+            node = Node::Binary(operator, Box::new(node), Box::new(self.factor()?));
+        }
+    }
+
+    fn factor(&mut self) -> Result<Node, ExpressionError>{
+        match self.next(){
+            Some(Token::Number(number)) => Ok(Node::Number(number)),
+            Some(Token::Metric(name)) => Ok(Node::Metric(name)),
+            Some(Token::Symbol('-')) => Ok(Node::Negate(Box::new(self.factor()?))),
+            Some(Token::Symbol('(')) => {
+                let node = self.sum()?;
+                self.expect(')')?;
+                Ok(node)
+            },
+            Some(Token::Identifier(name)) => {
+                let function = match name.as_str(){
+                    "min" => Function::Min,
+                    "max" => Function::Max,
+                    _ => return Err(ExpressionError::UnknownFunction(name))
+                };
+                self.expect('(')?;
+                let mut arguments = vec![self.sum()?];
+                while let Some(&Token::Symbol(',')) = self.peek(){
+                    self.next();
+                    arguments.push(self.sum()?);
+                }
+                self.expect(')')?;
+                Ok(Node::Function(function, arguments))
+            },
+            Some(token) => Err(ExpressionError::UnexpectedToken(token.to_string())),
+            None => Err(ExpressionError::UnexpectedEnd)
+        }
+    }
+}
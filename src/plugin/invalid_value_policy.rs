@@ -0,0 +1,19 @@
+/// Magnitude infinities are clamped to. Far below `f64::MAX`, so that the total and the sum of
+/// squares of an interval stay finite even if every one of 2^64 samples is clamped.
+pub const CLAMP_LIMIT: f64 = 1e144;
+
+///
+/// What to do with a reported value that is NaN or infinite. The NewRelic collector rejects a
+/// whole payload if a single metric contains such a value.
+///
+/// * `Reject` drops the value and returns `ReportError::InvalidValue`.
+/// * `Clamp` records infinities as `CLAMP_LIMIT` or `-CLAMP_LIMIT`. NaN cannot be clamped and is rejected.
+///
+/// Either way the value is counted in the metric's invalid samples.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InvalidValuePolicy{
+    #[default]
+    Reject,
+    Clamp
+}
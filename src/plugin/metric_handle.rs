@@ -0,0 +1,29 @@
+///
+/// Cheap, copyable reference to a metric, returned by `Agent::create_metric`. Recording through a
+/// handle goes straight to the metric's slot instead of looking the component GUID and metric
+/// name up on every report.
+///
+/// # Examples
+/// ```no_run
+/// # use newrelic_plugin::plugin::agent::Agent;
+/// let mut agent: Agent<()> = Agent::new("<license_key>".into(), "1.0.0".into(), "host".into(), 1234);
+/// let mut c1 = agent.create_component("Test Plugin".into(), "com.test_plugin.plugin_name".into());
+/// let requests = agent.create_metric(&mut c1, "Component/Request/Rate/host1[requests/second]".into()).unwrap();
+/// agent.register_component(c1).unwrap();
+///
+/// agent.run(move |agent| {
+///     agent.record(requests, 1000f64).unwrap();
+/// });
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct MetricHandle{
+    pub(crate) component: usize,
+    pub(crate) metric: usize,
+    pub(crate) generation: u32
+}
+
+impl MetricHandle{
+    pub(crate) fn new(component: usize, metric: usize, generation: u32) -> Self{
+        MetricHandle{ component, metric, generation }
+    }
+}
@@ -0,0 +1,39 @@
+///
+/// How reported values of a metric are interpreted.
+///
+/// * `Timeslice` aggregates every reported value into the interval's summary. Nothing is carried
+///   over to the next interval.
+/// * `Gauge` aggregates like `Timeslice`, but is meant for levels such as a queue depth: if no value
+///   was reported in an interval, the last reported value is delivered again.
+/// * `Counter` takes raw readings of a monotonic counter and records the increase since the
+///   previous reading. If the metric's unit is a rate (e.g. `[requests/second]`) the increase is
+///   divided by the real time elapsed between the two readings. The first reading only primes
+///   the counter. A reading lower than the previous one is treated as a wraparound when
+///   `wraps_at` is set (the counter's modulus, e.g. `2^32`) and as a counter reset otherwise.
+///   The last reading is kept across intervals so no increase is lost between deliveries.
+/// * `Histogram` aggregates like `Timeslice` and additionally keeps the distribution of the
+///   interval's values. At deliver time each of `percentiles` is sent as a companion metric
+///   named after the percentile, e.g. `Component/Latency/p99[ms]` for `Component/Latency[ms]`.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MetricKind{
+    #[default]
+    Timeslice,
+    Gauge,
+    Counter{ wraps_at: Option<f64> },
+    Histogram{ percentiles: Vec<f64> }
+}
+
+impl MetricKind{
+    pub fn counter() -> Self{
+        MetricKind::Counter{ wraps_at: None }
+    }
+
+    pub fn wrapping_counter(wraps_at: f64) -> Self{
+        MetricKind::Counter{ wraps_at: Some(wraps_at) }
+    }
+
+    pub fn histogram(percentiles: &[f64]) -> Self{
+        MetricKind::Histogram{ percentiles: percentiles.to_vec() }
+    }
+}
@@ -0,0 +1,166 @@
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+const PREFIX: &str = "Component";
+const MAX_LENGTH: usize = 255;
+
+///
+/// Validated NewRelic Plugin API metric name of the form `Component/<segment>/...[<unit>]`.
+/// Names can be parsed from strings or assembled with a `MetricNameBuilder`.
+///
+/// # Examples
+/// ```
+/// use newrelic_plugin::plugin::metric_name::MetricName;
+///
+/// let name = MetricName::builder()
+///     .segment("Request").segment("Rate").segment("host1")
+///     .unit("requests/second")
+///     .build().unwrap();
+/// assert_eq!(name.to_string(), "Component/Request/Rate/host1[requests/second]");
+/// assert_eq!(name, "Component/Request/Rate/host1[requests/second]".parse().unwrap());
+/// assert!(MetricName::parse("Request/Rate/host1[requests/second]").is_err());
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct MetricName{
+    segments: Vec<String>,
+    unit: String
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MetricNameError{
+    MissingPrefix,
+    MissingSegments,
+    EmptySegment,
+    MissingUnit,
+    InvalidCharacter(char),
+    TooLong(usize)
+}
+
+impl fmt::Display for MetricNameError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self{
+            MetricNameError::MissingPrefix => write!(f, "Metric name must start with \"{}/\"", PREFIX),
+            MetricNameError::MissingSegments => write!(f, "Metric name has no segments after \"{}/\"", PREFIX),
+            MetricNameError::EmptySegment => write!(f, "Metric name contains an empty segment"),
+            MetricNameError::MissingUnit => write!(f, "Metric name must end with a unit in brackets, e.g. [bytes]"),
+            MetricNameError::InvalidCharacter(c) => write!(f, "Metric name contains invalid character {:?}", c),
+            MetricNameError::TooLong(length) =>
+                write!(f, "Metric name is {} characters long, limit is {}", length, MAX_LENGTH)
+        }
+    }
+}
+
+impl Error for MetricNameError {}
+
+impl fmt::Display for MetricName {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}/{}[{}]", PREFIX, self.segments.join("/"), self.unit)
+    }
+}
+
+impl FromStr for MetricName {
+    type Err = MetricNameError;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err>{
+        MetricName::parse(name)
+    }
+}
+
+impl MetricName{
+    pub fn builder() -> MetricNameBuilder{
+        MetricNameBuilder::default()
+    }
+
+    pub fn parse(name: &str) -> Result<Self, MetricNameError>{
+        let path = match name.strip_prefix(PREFIX).and_then(|rest| rest.strip_prefix('/')){
+            Some(path) => path,
+            None => return Err(MetricNameError::MissingPrefix)
+        };
+        if !path.ends_with(']'){
+            return Err(MetricNameError::MissingUnit);
+        }
+        let open = match path.rfind('['){
+            Some(open) => open,
+            None => return Err(MetricNameError::MissingUnit)
+        };
+        let segments = path[..open].split('/').map(String::from).collect();
+        let unit = path[open + 1..path.len() - 1].to_string();
+        MetricName::new(segments, unit)
+    }
+
+    fn new(segments: Vec<String>, unit: String) -> Result<Self, MetricNameError>{
+        if segments.is_empty(){
+            return Err(MetricNameError::MissingSegments);
+        }
+        for segment in &segments{
+            if segment.is_empty(){
+                return Err(MetricNameError::EmptySegment);
+            }
+            check_characters(segment, &['/', '[', ']'])?;
+        }
+        if unit.is_empty(){
+            return Err(MetricNameError::MissingUnit);
+        }
+        check_characters(&unit, &['[', ']'])?;
+        let name = MetricName{ segments, unit };
+        let length = name.to_string().chars().count();
+        if length > MAX_LENGTH{
+            return Err(MetricNameError::TooLong(length));
+        }
+        Ok(name)
+    }
+
+    /// Name with `segment` appended to the path and the same unit,
+    /// e.g. `Component/Latency[ms]` -> `Component/Latency/p99[ms]`.
+    pub fn child(&self, segment: &str) -> Result<Self, MetricNameError>{
+        let mut segments = self.segments.clone();
+        segments.push(segment.to_string());
+        MetricName::new(segments, self.unit.clone())
+    }
+
+    /// Name with the last path segment replaced by `segment` and the same unit.
+    pub fn with_last_segment(&self, segment: &str) -> Result<Self, MetricNameError>{
+        let mut segments = self.segments.clone();
+        segments.pop();
+        segments.push(segment.to_string());
+        MetricName::new(segments, self.unit.clone())
+    }
+
+    pub fn segments(&self) -> &[String]{
+        &self.segments
+    }
+
+    pub fn unit(&self) -> &str{
+        &self.unit
+    }
+}
+
+fn check_characters(text: &str, reserved: &[char]) -> Result<(), MetricNameError>{
+    match text.chars().find(|c| c.is_control() || reserved.contains(c)){
+        Some(c) => Err(MetricNameError::InvalidCharacter(c)),
+        None => Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct MetricNameBuilder{
+    segments: Vec<String>,
+    unit: String
+}
+
+impl MetricNameBuilder{
+    pub fn segment<S: Into<String>>(mut self, segment: S) -> Self{
+        self.segments.push(segment.into());
+        self
+    }
+
+    pub fn unit<S: Into<String>>(mut self, unit: S) -> Self{
+        self.unit = unit.into();
+        self
+    }
+
+    pub fn build(self) -> Result<MetricName, MetricNameError>{
+        MetricName::new(self.segments, self.unit)
+    }
+}
@@ -0,0 +1,131 @@
+use plugin::metric_name::{MetricName, MetricNameError};
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+///
+/// Metric name with `{label}` placeholders, e.g. `Component/Queue/{queue}/Depth[messages]`.
+/// Concrete metric names are rendered from label values when a value is reported.
+///
+/// # Examples
+/// ```
+/// use newrelic_plugin::plugin::metric_template::MetricTemplate;
+///
+/// let template = MetricTemplate::parse("Component/Queue/{queue}/Depth[messages]").unwrap();
+/// assert_eq!(template.labels(), vec!["queue"]);
+/// let name = template.render(&[("queue", "emails")]).unwrap();
+/// assert_eq!(name.to_string(), "Component/Queue/emails/Depth[messages]");
+/// assert!(template.render(&[("queue", "a/b")]).is_err());
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct MetricTemplate{
+    template: String,
+    parts: Vec<Part>
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Part{
+    Text(String),
+    Label(String)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TemplateError{
+    UnclosedPlaceholder,
+    EmptyLabel,
+    MissingLabel(String),
+    InvalidLabelValue{ label: String, value: String },
+    InvalidName(MetricNameError)
+}
+
+impl fmt::Display for TemplateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self{
+            TemplateError::UnclosedPlaceholder => write!(f, "Metric template has an unclosed {{ placeholder"),
+            TemplateError::EmptyLabel => write!(f, "Metric template has an empty {{}} placeholder"),
+            TemplateError::MissingLabel(ref label) => write!(f, "No value given for label {}", label),
+            TemplateError::InvalidLabelValue{ ref label, ref value } =>
+                write!(f, "Value {:?} of label {} is empty or contains '/', '[', ']' or control characters", value, label),
+            TemplateError::InvalidName(ref e) => write!(f, "{}", e)
+        }
+    }
+}
+
+impl Error for TemplateError {}
+
+impl From<MetricNameError> for TemplateError {
+    fn from(e: MetricNameError) -> Self{
+        TemplateError::InvalidName(e)
+    }
+}
+
+impl fmt::Display for MetricTemplate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.template)
+    }
+}
+
+impl FromStr for MetricTemplate {
+    type Err = TemplateError;
+
+    fn from_str(template: &str) -> Result<Self, Self::Err>{
+        MetricTemplate::parse(template)
+    }
+}
+
+impl MetricTemplate{
+    pub fn parse(template: &str) -> Result<Self, TemplateError>{
+        let mut parts = vec![];
+        let mut rest = template;
+        while let Some(open) = rest.find('{'){
+            if open > 0{
+                parts.push(Part::Text(rest[..open].to_string()));
+            }
+            let close = match rest[open..].find('}'){
+                Some(close) => open + close,
+                None => return Err(TemplateError::UnclosedPlaceholder)
+            };
+            let label = &rest[open + 1..close];
+            if label.is_empty(){
+                return Err(TemplateError::EmptyLabel);
+            }
+            parts.push(Part::Label(label.to_string()));
+            rest = &rest[close + 1..];
+        }
+        if !rest.is_empty(){
+            parts.push(Part::Text(rest.to_string()));
+        }
+        let template = MetricTemplate{ template: template.to_string(), parts };
+        // Any valid label value must produce a valid name.
+        let sample: Vec<(&str, &str)> = template.labels().into_iter().map(|label| (label, "x")).collect();
+        template.render(&sample)?;
+        Ok(template)
+    }
+
+    pub fn labels(&self) -> Vec<&str>{
+        self.parts.iter().filter_map(|part| match *part{
+            Part::Label(ref label) => Some(label.as_str()),
+            Part::Text(_) => None
+        }).collect()
+    }
+
+    pub fn render(&self, labels: &[(&str, &str)]) -> Result<MetricName, TemplateError>{
+        let mut name = String::new();
+        for part in &self.parts{
+            match *part{
+                Part::Text(ref text) => name.push_str(text),
+                Part::Label(ref label) => {
+                    let value = match labels.iter().find(|&&(key, _)| key == label){
+                        Some(&(_, value)) => value,
+                        None => return Err(TemplateError::MissingLabel(label.clone()))
+                    };
+                    if value.is_empty() || value.chars().any(|c| c.is_control() || c == '/' || c == '[' || c == ']'){
+                        return Err(TemplateError::InvalidLabelValue{ label: label.clone(), value: value.to_string() });
+                    }
+                    name.push_str(value);
+                }
+            }
+        }
+        Ok(MetricName::parse(&name)?)
+    }
+}
@@ -1 +1,20 @@
-pub mod agent;
\ No newline at end of file
+pub mod agent;
+pub mod error;
+pub mod summary;
+pub mod statistics;
+pub mod metric_name;
+pub mod metric_template;
+pub mod attributes;
+pub mod unit;
+pub mod metric_kind;
+pub mod empty_policy;
+pub mod invalid_value_policy;
+pub mod reset_policy;
+pub mod metric_handle;
+pub mod rollup;
+pub mod expression;
+pub mod smoothing;
+pub mod recorder;
+pub mod snapshot;
+pub mod discovery;
+pub mod timer;
\ No newline at end of file
@@ -0,0 +1,245 @@
+use binding::histogram::Histogram;
+use plugin::invalid_value_policy::{InvalidValuePolicy, CLAMP_LIMIT};
+use plugin::metric_handle::MetricHandle;
+use plugin::summary::{MetricSummary, SummaryError};
+use plugin::unit::{Quantity, Unit};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Instant;
+
+/// Number of independently locked buffers, so threads recording different metrics rarely contend.
+const SHARDS: usize = 16;
+
+type Shard = Mutex<HashMap<MetricHandle, Pending>>;
+
+///
+/// Cloneable, `Send + Sync` handle for recording metrics from any thread, returned by
+/// `Agent::recorder`. Values are aggregated per metric as they are recorded and applied to the
+/// metrics by the agent loop at the end of each poll cycle, before metrics are delivered, so
+/// memory only grows with the number of metrics recorded to, not with the number of values.
+///
+/// Counters only keep their latest reading and the time it was taken, so rates are computed
+/// from when the readings were recorded rather than from when they were applied. A counter
+/// that wraps around or resets between two of its readings in the same poll cycle therefore
+/// looks like a single reset.
+///
+/// Because values are applied later, errors such as unknown handles are not returned to the
+/// recording thread; they are logged when the agent applies the values. Invalid values follow
+/// the `invalid_values` policy and are counted in the metric's invalid samples.
+///
+/// # Examples
+/// ```no_run
+/// # use newrelic_plugin::plugin::agent::Agent;
+/// use std::thread;
+///
+/// let mut agent: Agent<()> = Agent::new("<license_key>".into(), "1.0.0".into(), "host".into(), 1234);
+/// let mut c1 = agent.create_component("Test Plugin".into(), "com.test_plugin.plugin_name".into());
+/// let latency = agent.create_metric(&mut c1, "Component/Request/Latency[ms]".into()).unwrap();
+/// agent.register_component(c1).unwrap();
+///
+/// let recorder = agent.recorder();
+/// thread::spawn(move || agent.run(|_| {}));
+///
+/// let workers: Vec<_> = (0..4).map(|_| {
+///     let recorder = recorder.clone();
+///     thread::spawn(move || recorder.record(latency, 12.5))
+/// }).collect();
+/// for worker in workers{
+///     worker.join().unwrap();
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Recorder{
+    shards: Arc<Vec<Shard>>,
+    invalid_value_policy: InvalidValuePolicy
+}
+
+/// Values recorded in one unit since the last drain.
+#[derive(Debug, Clone)]
+pub(crate) struct Samples{
+    pub summary: MetricSummary,
+    pub histogram: Histogram,
+    pub last: Option<(f64, Instant)>
+}
+
+/// Everything recorded to one metric since the last drain.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Pending{
+    pub values: Samples,
+    pub quantities: Vec<(Unit, Samples)>,
+    pub summary: MetricSummary,
+    pub invalid_samples: u64
+}
+
+impl Default for Samples {
+    fn default() -> Self{
+        Samples{
+            summary: MetricSummary::empty(),
+            histogram: Histogram::new(),
+            last: None
+        }
+    }
+}
+
+impl Samples{
+    fn record(&mut self, value: f64, at: Instant){
+        self.summary.merge(&MetricSummary::from_value(value));
+        self.histogram.record(value);
+        self.last = Some((value, at));
+    }
+
+    /// The same samples with every value multiplied by the positive `factor`.
+    pub fn scaled(&self, factor: f64) -> Samples{
+        let mut histogram = Histogram::new();
+        histogram.merge_scaled(&self.histogram, factor);
+        Samples{
+            summary: MetricSummary{
+                count: self.summary.count,
+                total: self.summary.total * factor,
+                min: self.summary.min * factor,
+                max: self.summary.max * factor,
+                sum_of_squares: self.summary.sum_of_squares * factor * factor
+            },
+            histogram,
+            last: self.last.map(|(value, at)| (value * factor, at))
+        }
+    }
+}
+
+impl Recorder{
+    pub(crate) fn new(invalid_value_policy: InvalidValuePolicy) -> Self{
+        Recorder{
+            shards: Arc::new((0..SHARDS).map(|_| Mutex::new(HashMap::new())).collect()),
+            invalid_value_policy
+        }
+    }
+
+    fn shard(&self, handle: MetricHandle) -> MutexGuard<'_, HashMap<MetricHandle, Pending>>{
+        let shard = &self.shards[(handle.component.wrapping_mul(31) ^ handle.metric) % SHARDS];
+        // A thread that panicked while recording cannot leave the aggregates inconsistent.
+        match shard.lock(){
+            Ok(pending) => pending,
+            Err(poisoned) => poisoned.into_inner()
+        }
+    }
+
+    /// Value to record under the invalid values policy, or `None` if it is dropped.
+    fn check_value(&self, value: f64) -> Option<f64>{
+        match self.invalid_value_policy{
+            _ if value.is_finite() => Some(value),
+            InvalidValuePolicy::Clamp if !value.is_nan() => Some(value.clamp(-CLAMP_LIMIT, CLAMP_LIMIT)),
+            _ => None
+        }
+    }
+
+    pub fn record(&self, handle: MetricHandle, value: f64){
+        let at = Instant::now();
+        let mut shard = self.shard(handle);
+        let pending = shard.entry(handle).or_default();
+        match self.check_value(value){
+            Some(value) => pending.values.record(value, at),
+            None => pending.invalid_samples += 1
+        }
+    }
+
+    pub fn record_summary(&self, handle: MetricHandle, summary: MetricSummary){
+        let mut shard = self.shard(handle);
+        let pending = shard.entry(handle).or_default();
+        match summary.validate(){
+            Ok(()) => pending.summary.merge(&summary),
+            Err(SummaryError::NonFinite) => pending.invalid_samples += summary.count,
+            Err(e) => error!(target: "agent", "Dropping summary {} recorded to metric {:?}. Error: {}", summary, handle, e)
+        }
+    }
+
+    pub fn record_quantity<Q: Into<Quantity>>(&self, handle: MetricHandle, quantity: Q){
+        let quantity = quantity.into();
+        let at = Instant::now();
+        let mut shard = self.shard(handle);
+        let pending = shard.entry(handle).or_default();
+        let value = match self.check_value(quantity.value){
+            Some(value) => value,
+            None => {
+                pending.invalid_samples += 1;
+                return;
+            }
+        };
+        match pending.quantities.iter().position(|(unit, _)| *unit == quantity.unit){
+            Some(index) => pending.quantities[index].1.record(value, at),
+            None => {
+                let mut samples = Samples::default();
+                samples.record(value, at);
+                pending.quantities.push((quantity.unit, samples));
+            }
+        }
+    }
+
+    /// Takes everything recorded since the last drain, leaving the aggregates empty.
+    pub(crate) fn drain(&self) -> Vec<(MetricHandle, Pending)>{
+        let mut pending = vec![];
+        for shard in self.shards.iter(){
+            let mut shard = match shard.lock(){
+                Ok(shard) => shard,
+                Err(poisoned) => poisoned.into_inner()
+            };
+            pending.extend(shard.drain());
+        }
+        pending
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Recorder;
+    use plugin::invalid_value_policy::InvalidValuePolicy;
+    use plugin::metric_handle::MetricHandle;
+    use plugin::summary::MetricSummary;
+    use plugin::unit::{Quantity, Unit};
+    use std::thread;
+
+    #[test]
+    fn values_are_aggregated_per_metric_until_drained(){
+        let recorder = Recorder::new(InvalidValuePolicy::Reject);
+        let latency = MetricHandle::new(0, 0, 0);
+        let workers: Vec<_> = (0..4).map(|_| {
+            let recorder = recorder.clone();
+            thread::spawn(move || {
+                for value in 1..1001{
+                    recorder.record(latency, value as f64);
+                }
+            })
+        }).collect();
+        for worker in workers{
+            worker.join().unwrap();
+        }
+        recorder.record(latency, f64::NAN);
+        recorder.record_quantity(latency, Quantity::new(2f64, Unit::Seconds));
+        recorder.record_summary(latency, MetricSummary::from_samples(&[1f64, 3f64]).unwrap());
+
+        let drained = recorder.drain();
+        assert_eq!(drained.len(), 1);
+        let (handle, ref pending) = drained[0];
+        assert_eq!(handle, latency);
+        assert_eq!(pending.values.summary.count, 4000);
+        assert_eq!(pending.values.summary.total, 4f64 * 500500f64);
+        assert_eq!(pending.values.last.map(|(value, _)| value), Some(1000f64));
+        assert_eq!(pending.quantities.len(), 1);
+        assert_eq!(pending.quantities[0].0, Unit::Seconds);
+        assert_eq!(pending.quantities[0].1.scaled(1000f64).summary, MetricSummary::from_value(2000f64));
+        assert_eq!(pending.summary, MetricSummary::from_samples(&[1f64, 3f64]).unwrap());
+        assert_eq!(pending.invalid_samples, 1);
+        assert!(recorder.drain().is_empty());
+    }
+
+    #[test]
+    fn infinities_are_clamped_under_the_clamp_policy(){
+        let recorder = Recorder::new(InvalidValuePolicy::Clamp);
+        let handle = MetricHandle::new(0, 0, 0);
+        recorder.record(handle, f64::INFINITY);
+        recorder.record(handle, f64::NAN);
+        let (_, pending) = recorder.drain().remove(0);
+        assert_eq!(pending.values.summary.count, 1);
+        assert!(pending.values.summary.is_finite());
+        assert_eq!(pending.invalid_samples, 1);
+    }
+}
@@ -0,0 +1,19 @@
+use std::time::Duration;
+
+///
+/// When a metric's aggregated values are cleared after a successful delivery.
+///
+/// * `OnDeliver` starts every deliver interval from scratch.
+/// * `Never` keeps aggregating for the lifetime of the agent, e.g. for lifetime totals or
+///   configuration values.
+/// * `Every(period)` clears the values at the first delivery at least `period` after the last reset.
+///
+/// A component's reported `duration` is always the time since its last delivery, so the values
+/// of `Never` and `Every` metrics cover a longer span than the duration they are sent with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ResetPolicy{
+    #[default]
+    OnDeliver,
+    Never,
+    Every(Duration)
+}
@@ -0,0 +1,82 @@
+use plugin::metric_name::{MetricName, MetricNameError};
+use plugin::summary::MetricSummary;
+
+const WILDCARD: &str = "*";
+
+///
+/// How the metrics matched by a rollup rule are combined.
+///
+/// * `Sum` adds up the interval average of each matched metric, e.g. per-host request rates
+///   into a rate for all hosts.
+/// * `Merge` merges the matched summaries as if all samples had been reported to one metric.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rollup{
+    Sum,
+    Merge
+}
+
+///
+/// Rule producing a rollup metric from all metrics of a component whose names match a pattern.
+/// A `*` segment in the pattern matches any single segment; the unit must match exactly.
+///
+/// # Examples
+/// ```
+/// use newrelic_plugin::plugin::rollup::{Rollup, RollupRule};
+/// use newrelic_plugin::plugin::summary::MetricSummary;
+///
+/// let rule = RollupRule::parse("Component/Request/Rate/*[requests/second]",
+///     "Component/Request/Rate/all[requests/second]", Rollup::Sum).unwrap();
+/// assert!(rule.matches("Component/Request/Rate/host1[requests/second]"));
+/// assert!(!rule.matches("Component/Request/Rate/all[requests/second]"));
+/// assert!(!rule.matches("Component/Request/Rate/host1[requests/minute]"));
+///
+/// let summaries = [MetricSummary::from_value(10.0), MetricSummary::from_samples(&[4.0, 6.0]).unwrap()];
+/// assert_eq!(rule.combine(&summaries), Some(MetricSummary::from_value(15.0)));
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct RollupRule{
+    pattern: MetricName,
+    target: MetricName,
+    rollup: Rollup
+}
+
+impl RollupRule{
+    pub fn parse(pattern: &str, target: &str, rollup: Rollup) -> Result<Self, MetricNameError>{
+        Ok(RollupRule{
+            pattern: MetricName::parse(pattern)?,
+            target: MetricName::parse(target)?,
+            rollup
+        })
+    }
+
+    pub fn target(&self) -> &MetricName{
+        &self.target
+    }
+
+    /// Whether `metric_name` contributes to the rollup. The target itself never matches.
+    pub fn matches(&self, metric_name: &str) -> bool{
+        let name = match MetricName::parse(metric_name){
+            Ok(name) => name,
+            Err(_) => return false
+        };
+        name != self.target
+            && name.unit() == self.pattern.unit()
+            && name.segments().len() == self.pattern.segments().len()
+            && name.segments().iter().zip(self.pattern.segments())
+                .all(|(segment, pattern)| pattern == WILDCARD || segment == pattern)
+    }
+
+    /// Rollup summary of the matched interval summaries, or `None` if none has samples.
+    pub fn combine(&self, summaries: &[MetricSummary]) -> Option<MetricSummary>{
+        let summaries: Vec<&MetricSummary> = summaries.iter().filter(|summary| !summary.is_empty()).collect();
+        if summaries.is_empty(){
+            return None;
+        }
+        match self.rollup{
+            Rollup::Sum => Some(MetricSummary::from_value(summaries.iter()
+                .map(|summary| summary.total / summary.count as f64).sum())),
+            Rollup::Merge => Some(summaries.iter()
+                .fold(MetricSummary::empty(), |rollup, summary| rollup.merged(summary)))
+        }
+    }
+}
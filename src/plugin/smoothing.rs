@@ -0,0 +1,36 @@
+use std::fmt;
+
+///
+/// Windowed aggregation maintained across deliveries and sent as a companion metric next to the
+/// metric it smooths, e.g. `Component/Connections/ewma[connections]`. Each reported value (or the
+/// average of a reported summary) counts as one poll.
+///
+/// * `Ewma{ alpha }` exponentially weighted moving average; `alpha` in `(0, 1]` is the weight of the newest poll.
+///   A metric has one `Ewma` at most, as every alpha is sent under the same `ewma` name.
+/// * `SlidingAverage{ window }` average of the last `window` polls.
+/// * `SlidingMax{ window }` maximum of the last `window` polls.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Smoothing{
+    Ewma{ alpha: f64 },
+    SlidingAverage{ window: usize },
+    SlidingMax{ window: usize }
+}
+
+impl fmt::Display for Smoothing {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self{
+            Smoothing::Ewma{ .. } => write!(f, "ewma"),
+            Smoothing::SlidingAverage{ window } => write!(f, "avg{}", window),
+            Smoothing::SlidingMax{ window } => write!(f, "max{}", window)
+        }
+    }
+}
+
+impl Smoothing{
+    pub fn is_valid(&self) -> bool{
+        match *self{
+            Smoothing::Ewma{ alpha } => alpha > 0f64 && alpha <= 1f64,
+            Smoothing::SlidingAverage{ window } | Smoothing::SlidingMax{ window } => window > 0
+        }
+    }
+}
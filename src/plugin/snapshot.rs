@@ -0,0 +1,52 @@
+use plugin::summary::MetricSummary;
+use plugin::attributes::Attributes;
+use std::collections::BTreeMap;
+
+///
+/// Immutable copy of the metric summaries the agent would deliver for the current interval,
+/// returned by `Agent::snapshot`. It includes companion, rollup and derived metrics and leaves
+/// out expired and empty metrics, exactly like the payload sent to NewRelic.
+///
+/// Metric names are kept without attributes; the attributes of the component and of each metric
+/// are listed separately, so they can be exported as dimensions.
+///
+/// Snapshots serialise with serde, e.g. to log them as JSON.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct Snapshot{
+    /// Unix timestamp the snapshot was taken at.
+    pub taken_at: i64,
+    pub components: Vec<ComponentSnapshot>
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ComponentSnapshot{
+    pub name: String,
+    pub guid: String,
+    /// Seconds covered by the interval.
+    pub duration: i64,
+    pub metrics: BTreeMap<String, MetricSummary>,
+    pub attributes: Attributes,
+    /// Attributes of the metrics that have any, by metric name.
+    pub metric_attributes: BTreeMap<String, Attributes>
+}
+
+impl Snapshot{
+    pub fn component(&self, guid: &str) -> Option<&ComponentSnapshot>{
+        self.components.iter().find(|component| component.guid == guid)
+    }
+}
+
+impl ComponentSnapshot{
+    pub fn metric(&self, name: &str) -> Option<&MetricSummary>{
+        self.metrics.get(name)
+    }
+
+    /// Attributes of metric `name`: the component's, overridden by the metric's own.
+    pub fn attributes_of(&self, name: &str) -> Attributes{
+        let mut attributes = self.attributes.clone();
+        if let Some(metric_attributes) = self.metric_attributes.get(name){
+            attributes.extend(metric_attributes.clone());
+        }
+        attributes
+    }
+}
@@ -0,0 +1,103 @@
+use plugin::summary::MetricSummary;
+use std::fmt;
+
+///
+/// Mean and variance of the samples recorded to a metric in the current interval, returned by
+/// `Agent::statistics`. Samples are accumulated with Welford's algorithm, so the variance of large
+/// values with a small spread does not lose its precision the way `sum_of_squares` does.
+/// The variance is the population variance of the samples.
+///
+/// # Examples
+/// ```
+/// use newrelic_plugin::plugin::statistics::Statistics;
+///
+/// let mut statistics = Statistics::default();
+/// for sample in &[1e9 + 4.0, 1e9 + 7.0, 1e9 + 13.0, 1e9 + 16.0]{
+///     statistics.record(*sample);
+/// }
+/// assert_eq!(statistics.mean(), Some(1e9 + 10.0));
+/// assert_eq!(statistics.variance(), Some(22.5));
+///
+/// let (mut left, mut right) = (Statistics::default(), Statistics::default());
+/// left.record(1e9 + 4.0);
+/// left.record(1e9 + 7.0);
+/// right.record(1e9 + 13.0);
+/// right.record(1e9 + 16.0);
+/// left.merge(&right);
+/// assert_eq!(left, statistics);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub struct Statistics{
+    count: u64,
+    mean: f64,
+    m2: f64
+}
+
+impl fmt::Display for Statistics {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Count: {}, Mean: {}, Std. dev.: {}",
+            self.count, self.mean, self.std_dev().unwrap_or(0f64))
+    }
+}
+
+impl Statistics{
+    /// Statistics of a summary reported as a whole. Its variance can only be derived from
+    /// `sum_of_squares`, so it is as precise as the summary itself.
+    pub fn from_summary(summary: &MetricSummary) -> Self{
+        if summary.is_empty(){
+            return Statistics::default();
+        }
+        let count = summary.count as f64;
+        let mean = summary.total / count;
+        Statistics{
+            count: summary.count,
+            mean,
+            m2: (summary.sum_of_squares - summary.total * mean).max(0f64)
+        }
+    }
+
+    pub fn count(&self) -> u64{
+        self.count
+    }
+
+    pub fn record(&mut self, value: f64){
+        self.count += 1;
+        let delta = value - self.mean;
+        self.mean += delta / self.count as f64;
+        self.m2 += delta * (value - self.mean);
+    }
+
+    /// Combines the statistics of two sample sets (Chan et al.).
+    pub fn merge(&mut self, other: &Statistics){
+        if other.count == 0{
+            return;
+        }
+        if self.count == 0{
+            *self = *other;
+            return;
+        }
+        let count = self.count + other.count;
+        let delta = other.mean - self.mean;
+        self.mean += delta * other.count as f64 / count as f64;
+        self.m2 += other.m2 + delta * delta * self.count as f64 * other.count as f64 / count as f64;
+        self.count = count;
+    }
+
+    pub fn mean(&self) -> Option<f64>{
+        if self.count == 0{
+            return None;
+        }
+        Some(self.mean)
+    }
+
+    pub fn variance(&self) -> Option<f64>{
+        if self.count == 0{
+            return None;
+        }
+        Some(self.m2 / self.count as f64)
+    }
+
+    pub fn std_dev(&self) -> Option<f64>{
+        self.variance().map(f64::sqrt)
+    }
+}
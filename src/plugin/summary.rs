@@ -0,0 +1,220 @@
+use std::error::Error;
+use std::fmt;
+
+///
+/// Timeslice summary of a batch of metric samples, in the shape the NewRelic Plugin API expects.
+/// A summary with `count == 0` is empty and acts as the identity for `merge`.
+///
+/// # Examples
+/// ```
+/// use newrelic_plugin::plugin::summary::MetricSummary;
+///
+/// let mut summary = MetricSummary::from_samples(&[2.0, 4.0]).unwrap();
+/// summary.merge(&MetricSummary::from_value(6.0));
+/// assert_eq!(summary.count, 3);
+/// assert_eq!(summary.total, 12.0);
+/// assert_eq!(summary.min, 2.0);
+/// assert_eq!(summary.max, 6.0);
+/// assert_eq!(summary.sum_of_squares, 56.0);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub struct MetricSummary{
+    pub count: u64,
+    pub total: f64,
+    pub min: f64,
+    pub max: f64,
+    pub sum_of_squares: f64
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SummaryError{
+    EmptyCount,
+    MinAboveMax{ min: f64, max: f64 },
+    NonFinite
+}
+
+impl fmt::Display for SummaryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self{
+            SummaryError::EmptyCount => write!(f, "Summary count must be greater than zero"),
+            SummaryError::MinAboveMax{ min, max } =>
+                write!(f, "Summary min ({}) is greater than max ({})", min, max),
+            SummaryError::NonFinite => write!(f, "Summary contains NaN or infinite values")
+        }
+    }
+}
+
+impl Error for SummaryError {}
+
+impl fmt::Display for MetricSummary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Total: {}, Count: {}, Min.: {}, Max.: {}, Sum of squares: {}",
+            self.total, self.count, self.min, self.max, self.sum_of_squares)
+    }
+}
+
+impl MetricSummary{
+    pub fn new(count: u64, total: f64, min: f64, max: f64,
+        sum_of_squares: f64) -> Result<Self, SummaryError>{
+        let summary = MetricSummary{ count, total, min, max, sum_of_squares };
+        summary.validate()?;
+        Ok(summary)
+    }
+
+    pub fn empty() -> Self{
+        MetricSummary::default()
+    }
+
+    pub fn from_value(value: f64) -> Self{
+        MetricSummary{
+            count: 1,
+            total: value,
+            min: value,
+            max: value,
+            sum_of_squares: value * value
+        }
+    }
+
+    pub fn from_samples(samples: &[f64]) -> Result<Self, SummaryError>{
+        if samples.is_empty(){
+            return Err(SummaryError::EmptyCount);
+        }
+        let mut summary = MetricSummary::empty();
+        for sample in samples{
+            summary.merge(&MetricSummary::from_value(*sample));
+        }
+        Ok(summary)
+    }
+
+    pub fn is_empty(&self) -> bool{
+        self.count == 0
+    }
+
+    pub fn validate(&self) -> Result<(), SummaryError>{
+        if self.count == 0{
+            return Err(SummaryError::EmptyCount);
+        }
+        if !self.is_finite(){
+            return Err(SummaryError::NonFinite);
+        }
+        if self.min > self.max{
+            return Err(SummaryError::MinAboveMax{ min: self.min, max: self.max });
+        }
+        Ok(())
+    }
+
+    pub fn is_finite(&self) -> bool{
+        self.total.is_finite() && self.min.is_finite() && self.max.is_finite() && self.sum_of_squares.is_finite()
+    }
+
+    ///
+    /// Merges `other` into this summary. Merging is associative and commutative, so the
+    /// summaries of any split of a sample set merge into the summary of the whole set.
+    ///
+    /// # Examples
+    /// ```
+    /// use newrelic_plugin::plugin::summary::MetricSummary;
+    ///
+    /// let samples = [7.0, -3.0, 12.5, 0.0, 4.25, 9.0, -1.5];
+    /// let whole = MetricSummary::from_samples(&samples).unwrap();
+    /// for split in 0..samples.len() + 1{
+    ///     let (left, right) = samples.split_at(split);
+    ///     let left = MetricSummary::from_samples(left).unwrap_or_default();
+    ///     let right = MetricSummary::from_samples(right).unwrap_or_default();
+    ///     assert_eq!(left.merged(&right), whole);
+    ///     assert_eq!(right.merged(&left), whole);
+    /// }
+    /// ```
+    pub fn merge(&mut self, other: &MetricSummary){
+        if other.is_empty(){
+            return;
+        }
+        if self.is_empty(){
+            *self = *other;
+            return;
+        }
+        self.count += other.count;
+        self.total += other.total;
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+        self.sum_of_squares += other.sum_of_squares;
+    }
+
+    pub fn merged(mut self, other: &MetricSummary) -> Self{
+        self.merge(other);
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MetricSummary;
+
+    /// Seeded xorshift64* generator, so failures are reproducible without extra dependencies.
+    struct Rng(u64);
+
+    impl Rng{
+        fn next(&mut self) -> u64{
+            self.0 ^= self.0 >> 12;
+            self.0 ^= self.0 << 25;
+            self.0 ^= self.0 >> 27;
+            self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+        }
+
+        fn below(&mut self, bound: usize) -> usize{
+            (self.next() % bound as u64) as usize
+        }
+
+        fn sample(&mut self) -> f64{
+            (self.next() >> 11) as f64 / (1u64 << 53) as f64 * 2e6 - 1e6
+        }
+    }
+
+    /// Merging adds in a different order than summarising, so sums may differ by rounding.
+    /// Samples are at most 1e6 in magnitude, which bounds the rounding error of near-zero totals.
+    fn assert_close(actual: f64, expected: f64, what: &str){
+        let tolerance = 1e-9 * expected.abs().max(1e6);
+        assert!((actual - expected).abs() <= tolerance, "{}: {} != {}", what, actual, expected);
+    }
+
+    fn summary_of(samples: &[f64]) -> MetricSummary{
+        MetricSummary::from_samples(samples).unwrap_or_default()
+    }
+
+    #[test]
+    fn merging_any_split_in_any_order_equals_summary_of_all_samples(){
+        let mut rng = Rng(0x9e37_79b9_7f4a_7c15);
+        for _ in 0..500{
+            let samples: Vec<f64> = (0..rng.below(40)).map(|_| rng.sample()).collect();
+
+            let mut cuts: Vec<usize> = (0..rng.below(6)).map(|_| rng.below(samples.len() + 1)).collect();
+            cuts.push(0);
+            cuts.push(samples.len());
+            cuts.sort();
+            let mut parts: Vec<MetricSummary> = cuts.windows(2)
+                .map(|cut| summary_of(&samples[cut[0]..cut[1]])).collect();
+            for i in (1..parts.len()).rev(){
+                parts.swap(i, rng.below(i + 1));
+            }
+
+            let merged = parts.iter().fold(MetricSummary::empty(), |merged, part| merged.merged(part));
+            let whole = summary_of(&samples);
+            assert_eq!(merged.count, whole.count);
+            assert_close(merged.total, whole.total, "total");
+            assert_close(merged.min, whole.min, "min");
+            assert_close(merged.max, whole.max, "max");
+            assert_close(merged.sum_of_squares, whole.sum_of_squares, "sum_of_squares");
+        }
+    }
+
+    #[test]
+    fn empty_summary_is_the_identity_of_merge(){
+        let mut rng = Rng(42);
+        for _ in 0..100{
+            let samples: Vec<f64> = (0..rng.below(10) + 1).map(|_| rng.sample()).collect();
+            let summary = summary_of(&samples);
+            assert_eq!(summary.merged(&MetricSummary::empty()), summary);
+            assert_eq!(MetricSummary::empty().merged(&summary), summary);
+        }
+    }
+}
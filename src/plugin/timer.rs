@@ -0,0 +1,98 @@
+use plugin::agent::Agent;
+use plugin::metric_handle::MetricHandle;
+use std::ops::{Deref, DerefMut};
+use std::time::{Duration, Instant};
+
+///
+/// Guard returned by `Agent::time`. When dropped it reports the time elapsed since its creation
+/// to the metric, converted to the metric's unit. The guard dereferences to the agent, so the
+/// timed code can keep reporting other metrics.
+///
+/// # Examples
+/// ```no_run
+/// # use newrelic_plugin::plugin::agent::Agent;
+/// fn cycle(agent: &mut Agent<()>){
+///     let poll_duration = agent.metric_handle(
+///         "com.test_plugin.plugin_name", "Component/Poll/Duration[ms]").unwrap();
+///     let mut timer = agent.time(poll_duration);
+///     // ... poll the monitored service, reporting through the guard ...
+///     timer.report_metric(
+///         "com.test_plugin.plugin_name".into(),
+///         "Component/Request/Rate/host1[requests/second]".into(),
+///         1000f64
+///     ).unwrap();
+/// }
+/// ```
+pub struct Timer<'a, T: 'a>{
+    agent: &'a mut Agent<T>,
+    handle: MetricHandle,
+    start: Instant
+}
+
+impl<'a, T> Timer<'a, T>{
+    pub fn new(agent: &'a mut Agent<T>, handle: MetricHandle) -> Self{
+        Timer{
+            agent,
+            handle,
+            start: Instant::now()
+        }
+    }
+
+    pub fn elapsed(&self) -> Duration{
+        self.start.elapsed()
+    }
+}
+
+impl<'a, T> Deref for Timer<'a, T> {
+    type Target = Agent<T>;
+
+    fn deref(&self) -> &Agent<T>{
+        self.agent
+    }
+}
+
+impl<'a, T> DerefMut for Timer<'a, T> {
+    fn deref_mut(&mut self) -> &mut Agent<T>{
+        self.agent
+    }
+}
+
+impl<'a, T> Drop for Timer<'a, T> {
+    fn drop(&mut self){
+        let elapsed = self.elapsed();
+        if let Err(e) = self.agent.record_quantity(self.handle, elapsed){
+            error!(target: "agent", "Could not record timing of metric {:?}. Error: {}", self.handle, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use binding::config::Config;
+    use plugin::agent::Agent;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn dropping_the_guard_records_elapsed_time_in_the_metric_unit(){
+        let mut agent: Agent<()> = Agent::with_config("<license_key>".into(), "1.0.0".into(), "host".into(), 1234, Config::new());
+        let mut component = agent.create_component("Test Plugin".into(), "com.test_plugin.timer".into());
+        let poll = agent.create_metric(&mut component, "Component/Poll/Duration[ms]".into()).unwrap();
+        agent.register_component(component).unwrap();
+
+        {
+            let timer = agent.time(poll);
+            thread::sleep(Duration::from_millis(20));
+            assert!(timer.elapsed() >= Duration::from_millis(20));
+        }
+        let result = agent.time_fn(poll, |_| 42);
+        assert_eq!(result, 42);
+
+        let snapshot = agent.snapshot();
+        let summary = snapshot.component("com.test_plugin.timer").unwrap()
+            .metric("Component/Poll/Duration[ms]").unwrap();
+        assert_eq!(summary.count, 2);
+        assert!(summary.max >= 20f64 && summary.max < 1000f64);
+        assert!(summary.min < summary.max);
+    }
+}
@@ -0,0 +1,164 @@
+use std::error::Error;
+use std::fmt;
+use std::time::Duration;
+
+///
+/// Unit of a metric, as declared in brackets at the end of its name (e.g. `[bytes]`, `[requests/second]`).
+/// Data sizes use binary multiples (1 kilobyte = 1024 bytes).
+///
+/// # Examples
+/// ```
+/// use newrelic_plugin::plugin::unit::Unit;
+///
+/// assert_eq!(Unit::parse("ms"), Unit::Milliseconds);
+/// assert_eq!(Unit::parse("requests/second"), Unit::PerSecond("requests".into()));
+/// assert_eq!(Unit::Kilobytes.convert(2.0, &Unit::Bytes).unwrap(), 2048.0);
+/// assert_eq!(Unit::PerMinute("jobs".into()).convert(120.0, &Unit::PerSecond("jobs".into())).unwrap(), 2.0);
+/// assert!(Unit::Bytes.convert(1.0, &Unit::Seconds).is_err());
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Unit{
+    Bytes,
+    Kilobytes,
+    Megabytes,
+    Gigabytes,
+    Microseconds,
+    Milliseconds,
+    Seconds,
+    Minutes,
+    Percent,
+    PerSecond(String),
+    PerMinute(String),
+    Custom(String)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum UnitError{
+    Incompatible{ from: Unit, to: Unit }
+}
+
+impl fmt::Display for UnitError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self{
+            UnitError::Incompatible{ ref from, ref to } =>
+                write!(f, "Cannot convert from [{}] to [{}]", from, to)
+        }
+    }
+}
+
+impl Error for UnitError {}
+
+impl fmt::Display for Unit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self{
+            Unit::Bytes => write!(f, "bytes"),
+            Unit::Kilobytes => write!(f, "kilobytes"),
+            Unit::Megabytes => write!(f, "megabytes"),
+            Unit::Gigabytes => write!(f, "gigabytes"),
+            Unit::Microseconds => write!(f, "microseconds"),
+            Unit::Milliseconds => write!(f, "ms"),
+            Unit::Seconds => write!(f, "seconds"),
+            Unit::Minutes => write!(f, "minutes"),
+            Unit::Percent => write!(f, "percent"),
+            Unit::PerSecond(ref what) => write!(f, "{}/second", what),
+            Unit::PerMinute(ref what) => write!(f, "{}/minute", what),
+            Unit::Custom(ref unit) => write!(f, "{}", unit)
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Dimension<'a>{
+    Data,
+    Time,
+    Ratio,
+    Rate(&'a str),
+    Custom(&'a str)
+}
+
+impl Unit{
+    pub fn parse(unit: &str) -> Self{
+        let unit = unit.trim();
+        match unit.to_lowercase().as_str(){
+            "b" | "byte" | "bytes" => Unit::Bytes,
+            "kb" | "kilobyte" | "kilobytes" => Unit::Kilobytes,
+            "mb" | "megabyte" | "megabytes" => Unit::Megabytes,
+            "gb" | "gigabyte" | "gigabytes" => Unit::Gigabytes,
+            "us" | "microsecond" | "microseconds" => Unit::Microseconds,
+            "ms" | "millisecond" | "milliseconds" => Unit::Milliseconds,
+            "s" | "sec" | "second" | "seconds" => Unit::Seconds,
+            "min" | "minute" | "minutes" => Unit::Minutes,
+            "%" | "percent" | "percentage" => Unit::Percent,
+            _ => {
+                if let Some(slash) = unit.rfind('/'){
+                    let what = unit[..slash].to_string();
+                    match unit[slash + 1..].to_lowercase().as_str(){
+                        "s" | "sec" | "second" => return Unit::PerSecond(what),
+                        "min" | "minute" => return Unit::PerMinute(what),
+                        _ => {}
+                    }
+                }
+                Unit::Custom(unit.to_string())
+            }
+        }
+    }
+
+    fn dimension(&self) -> Dimension<'_>{
+        match *self{
+            Unit::Bytes | Unit::Kilobytes | Unit::Megabytes | Unit::Gigabytes => Dimension::Data,
+            Unit::Microseconds | Unit::Milliseconds | Unit::Seconds | Unit::Minutes => Dimension::Time,
+            Unit::Percent => Dimension::Ratio,
+            Unit::PerSecond(ref what) | Unit::PerMinute(ref what) => Dimension::Rate(what),
+            Unit::Custom(ref unit) => Dimension::Custom(unit)
+        }
+    }
+
+    /// Size of one of this unit in the base unit of its dimension (bytes, seconds, per second).
+    fn factor(&self) -> f64{
+        match *self{
+            Unit::Kilobytes => 1024f64,
+            Unit::Megabytes => 1024f64 * 1024f64,
+            Unit::Gigabytes => 1024f64 * 1024f64 * 1024f64,
+            Unit::Microseconds => 1e-6,
+            Unit::Milliseconds => 1e-3,
+            Unit::Minutes => 60f64,
+            Unit::PerMinute(_) => 1f64 / 60f64,
+            _ => 1f64
+        }
+    }
+
+    pub fn convert(&self, value: f64, to: &Unit) -> Result<f64, UnitError>{
+        if self == to{
+            return Ok(value);
+        }
+        if self.dimension() != to.dimension(){
+            return Err(UnitError::Incompatible{ from: self.clone(), to: to.clone() });
+        }
+        Ok(value * self.factor() / to.factor())
+    }
+}
+
+///
+/// A value together with the unit it was measured in. Reported quantities are converted
+/// to the unit declared in the metric's name before aggregation.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Quantity{
+    pub value: f64,
+    pub unit: Unit
+}
+
+impl Quantity{
+    pub fn new(value: f64, unit: Unit) -> Self{
+        Quantity{ value, unit }
+    }
+
+    pub fn convert(&self, to: &Unit) -> Result<f64, UnitError>{
+        self.unit.convert(self.value, to)
+    }
+}
+
+impl From<Duration> for Quantity {
+    fn from(duration: Duration) -> Self{
+        Quantity::new(duration.as_secs_f64(), Unit::Seconds)
+    }
+}